@@ -0,0 +1,35 @@
+# frozen_string_literal: true
+
+# Measures how long System#update spends on handle cleanup with lots of live handles.
+#
+#   bundle exec rake compile && bundle exec ruby bench/extern_struct_storage.rb [handles] [frames]
+
+require "benchmark"
+require "libfmod"
+
+handles = Integer(ARGV[0] || 10_000)
+frames = Integer(ARGV[1] || 600)
+
+builder = FMOD::SystemBuilder.new
+builder.output(FMOD::OutputType::NoSoundNRT)
+system = builder.build(64, FMOD::InitFlags::NORMAL)
+
+def time_updates(system, frames)
+  Benchmark.realtime { frames.times { system.update } } / frames * 1000
+end
+
+baseline = time_updates(system, frames)
+
+dsps = Array.new(handles / 2) { system.create_dsp_by_type(FMOD::DspType::Mixer) }
+groups = Array.new(handles / 2) { |i| system.create_sound_group("group #{i}") }
+
+loaded = time_updates(system, frames)
+full = Benchmark.realtime { FMOD::ExternStructStorage.cleanup } * 1000
+
+puts format("%-32s %8.3f ms", "update, no handles", baseline)
+puts format("%-32s %8.3f ms", "update, #{handles} handles", loaded)
+puts format("%-32s %8.3f ms", "full cleanup, #{handles} handles", full)
+puts "cleanup budget: #{FMOD::ExternStructStorage.cleanup_budget} handles per update"
+
+(dsps + groups).each(&:release)
+system.release
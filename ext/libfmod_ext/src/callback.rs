@@ -4,31 +4,367 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use magnus::prelude::*;
-use once_cell::sync::OnceCell;
-use std::sync::mpsc::Sender;
+use magnus::{
+    prelude::*,
+    value::{InnerValue, Opaque},
+};
+use once_cell::sync::{Lazy, OnceCell};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        mpsc::Sender,
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
 
-use crate::thread;
+use crate::{
+    extern_struct_storage::{self, ExternStruct},
+    thread, Result,
+};
 
 type Callback = Box<dyn FnOnce(&magnus::Ruby) + Send>;
-static SENDER: OnceCell<Sender<Option<Callback>>> = OnceCell::new();
+// None when the callback thread isn't running (after FMOD.shutdown)
+static SENDER: Mutex<Option<Sender<Option<Callback>>>> = Mutex::new(None);
+static MODULE: OnceCell<Opaque<magnus::RModule>> = OnceCell::new();
+
+/// A queue that deferred callbacks are pushed onto until the owning system is updated.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum Queue {
+    System(fmod::System),
+    Studio(fmod::studio::System),
+}
+
+type DeferredCallback = Box<dyn FnOnce(&magnus::Ruby) -> Result<()> + Send>;
+// a queue only exists while deferred mode is enabled for it
+static DEFERRED: Lazy<Mutex<HashMap<Queue, Vec<DeferredCallback>>>> = Lazy::new(Default::default);
 
 pub fn process(callback: impl FnOnce(&magnus::Ruby) + Send + 'static) {
     if let Ok(ruby) = magnus::Ruby::get() {
         callback(&ruby);
         return;
     }
-    let sender = SENDER.get().expect("callback sender not initialized");
-    sender
-        .send(Some(Box::new(callback)))
-        .expect("calling thread is dead. please report this");
+    // if the callback thread is gone the callback is dropped, which `call` reports to FMOD as an error
+    let sender = SENDER.lock().unwrap();
+    if let Some(sender) = sender.as_ref() {
+        let _ = sender.send(Some(Box::new(callback)));
+    }
+}
+
+/// Runs `callback` on a Ruby thread and blocks until it has finished, or until the timeout set for `name` runs out.
+///
+/// If the callback raises, the exception is handed to `FMOD.callback_error_handler`
+/// (or queued to be raised from the next `update`) and FMOD gets `FMOD_ERR_INTERNAL` back.
+/// If it times out the stall is recorded in `FMOD.callback_stats` and FMOD gets `T::default()` instead.
+/// A callback that timed out before it started never runs, so it can't touch handles or buffers FMOD has moved on from.
+/// One that had already started is left to finish on its own, and whatever it returns is thrown away.
+pub fn call<T, F>(name: &'static str, object: &dyn Debug, callback: F) -> fmod::Result<T>
+where
+    T: Default + Send + 'static,
+    F: FnOnce(&magnus::Ruby) -> Result<T> + Send + 'static,
+{
+    call_with_timeout(name, object, timeout(name), callback)
+}
+
+/// Like [`call`], but never times out.
+///
+/// For callbacks that lend Ruby data borrowed from FMOD, which must not outlive the callback.
+pub fn call_blocking<T, F>(name: &'static str, object: &dyn Debug, callback: F) -> fmod::Result<T>
+where
+    T: Default + Send + 'static,
+    F: FnOnce(&magnus::Ruby) -> Result<T> + Send + 'static,
+{
+    call_with_timeout(name, object, None, callback)
+}
+
+fn call_with_timeout<T, F>(
+    name: &'static str,
+    object: &dyn Debug,
+    timeout: Option<Duration>,
+    callback: F,
+) -> fmod::Result<T>
+where
+    T: Default + Send + 'static,
+    F: FnOnce(&magnus::Ruby) -> Result<T> + Send + 'static,
+{
+    let start = Instant::now();
+    let (sender, reciever) = oneshot::channel();
+    let state = Arc::new(AtomicU8::new(PENDING));
+    let callback_state = state.clone();
+    process(move |ruby| {
+        if callback_state
+            .compare_exchange(PENDING, RUNNING, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            // abandoned, FMOD already got its answer
+            return;
+        }
+        let result = callback(ruby)
+            .map_err(|error| report_error(ruby, error))
+            .ok();
+        if callback_state.load(Ordering::Acquire) == ABANDONED {
+            // timed out while running, FMOD already got T::default()
+            return;
+        }
+        let _ = sender.send(result);
+    });
+    let result = match timeout {
+        Some(timeout) => match reciever.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(oneshot::RecvTimeoutError::Timeout) => {
+                // whether it's still pending or already running, FMOD doesn't wait for it any longer
+                state.store(ABANDONED, Ordering::Release);
+                record_call(name, object, start.elapsed(), true);
+                return Ok(T::default());
+            }
+            Err(oneshot::RecvTimeoutError::Disconnected) => None,
+        },
+        None => reciever.recv().ok().flatten(),
+    };
+    record_call(name, object, start.elapsed(), false);
+    result.ok_or(fmod::Error::Fmod(fmod::ffi::FMOD_RESULT::FMOD_ERR_INTERNAL))
+}
+
+// the state of a callback sent off by `call_with_timeout`
+const PENDING: u8 = 0;
+const RUNNING: u8 = 1;
+const ABANDONED: u8 = 2;
+
+/// Like [`call`], but if `queue` is in deferred mode the callback is queued and FMOD is not blocked.
+///
+/// Only callbacks that don't hand anything back to FMOD (and don't borrow data from it) should use this.
+pub fn defer<F>(
+    queue: Option<Queue>,
+    name: &'static str,
+    object: &dyn Debug,
+    callback: F,
+) -> fmod::Result<()>
+where
+    F: FnOnce(&magnus::Ruby) -> Result<()> + Send + 'static,
+{
+    if let Some(queue) = queue {
+        let mut deferred = DEFERRED.lock().unwrap();
+        if let Some(pending) = deferred.get_mut(&queue) {
+            pending.push(Box::new(callback));
+            return Ok(());
+        }
+    }
+    call(name, object, callback)
+}
+
+#[derive(Default)]
+struct CallbackStats {
+    calls: u64,
+    stalls: u64,
+    max_elapsed: Duration,
+    last_stall: Option<(String, Duration)>,
+}
+
+// timeouts are keyed by callback name, with None holding the default.
+// FMOD's threads look these up on every callback, so that shouldn't allocate or wait on a writer for long
+static TIMEOUTS: Lazy<RwLock<HashMap<Option<&'static str>, Duration>>> =
+    Lazy::new(Default::default);
+// callback names set from ruby, leaked once each so they can be used as keys
+static TIMEOUT_NAMES: Lazy<Mutex<HashSet<&'static str>>> = Lazy::new(Default::default);
+static STATS: Lazy<Mutex<HashMap<&'static str, CallbackStats>>> = Lazy::new(Default::default);
+
+fn timeout(name: &'static str) -> Option<Duration> {
+    let timeouts = TIMEOUTS.read().unwrap();
+    timeouts
+        .get(&Some(name))
+        .or_else(|| timeouts.get(&None))
+        .copied()
+}
+
+fn record_call(name: &'static str, object: &dyn Debug, elapsed: Duration, stalled: bool) {
+    let mut stats = STATS.lock().unwrap();
+    let stats = stats.entry(name).or_default();
+    stats.calls += 1;
+    stats.max_elapsed = stats.max_elapsed.max(elapsed);
+    if stalled {
+        stats.stalls += 1;
+        stats.last_stall = Some((format!("{object:?}"), elapsed));
+    }
+}
+
+pub fn is_deferred(queue: Queue) -> bool {
+    DEFERRED.lock().unwrap().contains_key(&queue)
+}
+
+/// Enables or disables deferred mode for `queue`. Anything still queued is run when disabling.
+pub fn set_deferred(queue: Queue, deferred: bool) {
+    if deferred {
+        DEFERRED.lock().unwrap().entry(queue).or_default();
+    } else {
+        drain(queue);
+        DEFERRED.lock().unwrap().remove(&queue);
+    }
+}
+
+/// Runs every callback queued on `queue` so far.
+///
+/// Callbacks queued while draining are left for the next call, so a callback that triggers more callbacks can't loop forever.
+pub fn drain(queue: Queue) {
+    let pending = match DEFERRED.lock().unwrap().get_mut(&queue) {
+        Some(pending) => std::mem::take(pending),
+        None => return,
+    };
+    let ruby = magnus::Ruby::get().unwrap();
+    for callback in pending {
+        if let Err(error) = callback(&ruby) {
+            report_error(&ruby, error);
+        }
+    }
+}
+
+/// Throws away a queue without running it, for when its system has been released.
+pub fn discard(queue: Queue) {
+    DEFERRED.lock().unwrap().remove(&queue);
+}
+
+fn module(ruby: &magnus::Ruby) -> magnus::RModule {
+    MODULE.get().unwrap().get_inner_with(ruby)
+}
+
+fn exception_from_error(ruby: &magnus::Ruby, error: magnus::Error) -> magnus::Exception {
+    // errors that don't carry an exception (throw/break out of a callback) become a RuntimeError
+    error
+        .value()
+        .and_then(magnus::Exception::from_value)
+        .unwrap_or_else(|| {
+            ruby.exception_runtime_error()
+                .new_instance(("non-local exit from an FMOD callback",))
+                .expect("failed to create RuntimeError")
+        })
+}
+
+fn queue_error(ruby: &magnus::Ruby, exception: magnus::Exception) {
+    let errors: magnus::RArray = module(ruby)
+        .ivar_get("__callback_errors")
+        .expect("callback error queue is missing");
+    let _ = errors.push(exception);
+}
+
+pub fn report_error(ruby: &magnus::Ruby, error: magnus::Error) {
+    let exception = exception_from_error(ruby, error);
+    let handler: Option<magnus::Value> = module(ruby)
+        .ivar_get("__callback_error_handler")
+        .ok()
+        .flatten();
+    let Some(handler) = handler else {
+        queue_error(ruby, exception);
+        return;
+    };
+    // an error handler that raises is reported the same way a callback would be
+    if let Err(error) = handler.funcall::<_, _, magnus::Value>("call", (exception,)) {
+        queue_error(ruby, exception_from_error(ruby, error));
+    }
+}
+
+/// Raises the oldest exception raised by a callback since the last call, if any.
+pub fn raise_pending_error() -> Result<()> {
+    let ruby = magnus::Ruby::get().unwrap();
+    let errors: magnus::RArray = module(&ruby).ivar_get("__callback_errors")?;
+    match errors.shift::<Option<magnus::Exception>>()? {
+        Some(exception) => Err(exception.into()),
+        None => Ok(()),
+    }
+}
+
+fn timeout_key(kind: Option<magnus::Symbol>) -> Result<Option<&'static str>> {
+    let Some(kind) = kind else {
+        return Ok(None);
+    };
+    let name = kind.name()?;
+    let mut names = TIMEOUT_NAMES.lock().unwrap();
+    if let Some(&name) = names.get(&*name) {
+        return Ok(Some(name));
+    }
+    let name: &'static str = Box::leak(name.into_owned().into_boxed_str());
+    names.insert(name);
+    Ok(Some(name))
+}
+
+// a nil kind sets the default timeout, and a nil timeout waits forever
+fn set_callback_timeout(
+    _: magnus::RModule,
+    kind: Option<magnus::Symbol>,
+    seconds: Option<f64>,
+) -> Result<()> {
+    let key = timeout_key(kind)?;
+    let mut timeouts = TIMEOUTS.write().unwrap();
+    match seconds {
+        Some(seconds) => {
+            let timeout = Duration::try_from_secs_f64(seconds)
+                .map_err(|e| magnus::Error::new(magnus::exception::arg_error(), e.to_string()))?;
+            timeouts.insert(key, timeout);
+        }
+        None => {
+            timeouts.remove(&key);
+        }
+    }
+    Ok(())
+}
+
+fn get_callback_timeout(_: magnus::RModule, kind: Option<magnus::Symbol>) -> Result<Option<f64>> {
+    let key = timeout_key(kind)?;
+    let timeouts = TIMEOUTS.read().unwrap();
+    Ok(timeouts.get(&key).map(Duration::as_secs_f64))
+}
+
+fn callback_stats(_: magnus::RModule) -> Result<magnus::RHash> {
+    let ruby = magnus::Ruby::get().unwrap();
+    let result = ruby.hash_new();
+    let stats = STATS.lock().unwrap();
+    for (&name, stats) in stats.iter() {
+        let hash = ruby.hash_new();
+        hash.aset(ruby.to_symbol("calls"), stats.calls)?;
+        hash.aset(ruby.to_symbol("stalls"), stats.stalls)?;
+        hash.aset(
+            ruby.to_symbol("max_elapsed"),
+            stats.max_elapsed.as_secs_f64(),
+        )?;
+        let last_stall = match &stats.last_stall {
+            Some((object, elapsed)) => {
+                let stall = ruby.hash_new();
+                stall.aset(ruby.to_symbol("object"), object.as_str())?;
+                stall.aset(ruby.to_symbol("elapsed"), elapsed.as_secs_f64())?;
+                stall.as_value()
+            }
+            None => ruby.qnil().as_value(),
+        };
+        hash.aset(ruby.to_symbol("last_stall"), last_stall)?;
+        result.aset(ruby.to_symbol(name), hash)?;
+    }
+    Ok(result)
+}
+
+fn reset_callback_stats(_: magnus::RModule) {
+    STATS.lock().unwrap().clear();
+}
+
+fn callback_error_handler(module: magnus::RModule) -> Result<magnus::Value> {
+    module.ivar_get("__callback_error_handler")
+}
+
+fn set_callback_error_handler(module: magnus::RModule, handler: magnus::Value) -> Result<()> {
+    if !handler.is_nil() && !handler.respond_to("call", false)? {
+        return Err(magnus::Error::new(
+            magnus::exception::type_error(),
+            "callback error handler must respond to #call",
+        ));
+    }
+    module.ivar_set("__callback_error_handler", handler)
 }
 
-pub fn bind() {
+fn start(ruby: &magnus::Ruby) -> Result<()> {
     let (sender, reciever) = std::sync::mpsc::channel();
-    SENDER.set(sender).unwrap();
+    let ubf_sender = sender.clone();
+    *SENDER.lock().unwrap() = Some(sender);
 
-    unsafe {
+    let callback_thread = unsafe {
         thread::spawn_ruby_thread(move |ruby| {
             thread::without_gvl(
                 || {
@@ -52,11 +388,99 @@ pub fn bind() {
                 },
                 || {
                     // we send None to signal that the thread is done
-                    let _ = SENDER.get().unwrap().send(None);
+                    let _ = ubf_sender.send(None);
                 },
             );
 
             ruby.qnil().as_value()
-        });
+        })
+    };
+    module(ruby).ivar_set("__callback_thread", callback_thread)
+}
+
+fn stop(ruby: &magnus::Ruby) -> Result<()> {
+    let Some(sender) = SENDER.lock().unwrap().take() else {
+        return Ok(());
+    };
+    // anything already sent is run before the thread sees this
+    let _ = sender.send(None);
+    let callback_thread: magnus::Value = module(ruby).ivar_get("__callback_thread")?;
+    let _: magnus::Value = callback_thread.funcall("join", ())?;
+    module(ruby).ivar_set("__callback_thread", ruby.qnil())
+}
+
+fn release_all(filter: impl Fn(&ExternStruct) -> bool) -> Result<()> {
+    let mut result = Ok(());
+    for system in extern_struct_storage::values_where(filter) {
+        if let Err(error) = system.funcall::<_, _, magnus::Value>("release", ()) {
+            result = result.and(Err(error));
+        }
+    }
+    result
+}
+
+/// Runs pending callbacks, stops the callback thread and releases every system that is still alive.
+fn shutdown(_: magnus::RModule) -> Result<()> {
+    let ruby = magnus::Ruby::get().unwrap();
+
+    let queues: Vec<Queue> = DEFERRED.lock().unwrap().keys().copied().collect();
+    for queue in queues {
+        drain(queue);
+    }
+    DEFERRED.lock().unwrap().clear();
+
+    // the thread is stopped before releasing anything, so a callback fired while a system is released
+    // gets an error back instead of waiting on a thread that can't get the GVL
+    stop(&ruby)?;
+
+    // studio systems own their core system, so they have to be released first
+    let studio = release_all(|key| matches!(key, ExternStruct::StudioSystem(_)));
+    extern_struct_storage::cleanup();
+    let core = release_all(|key| matches!(key, ExternStruct::System(_)));
+    extern_struct_storage::cleanup();
+
+    studio.and(core)
+}
+
+// called in the child by the Process._fork hook in lib/libfmod.rb
+fn after_fork(_: magnus::RModule) -> Result<()> {
+    let ruby = magnus::Ruby::get().unwrap();
+    // threads don't survive a fork, so the old sender leads nowhere
+    let was_running = SENDER.lock().unwrap().take().is_some();
+    if was_running {
+        start(&ruby)?;
     }
+    Ok(())
+}
+
+pub fn bind(module: magnus::RModule) -> Result<()> {
+    let ruby = magnus::Ruby::get().unwrap();
+    module.ivar_set("__callback_errors", ruby.ary_new())?;
+    module.ivar_set("__callback_error_handler", ruby.qnil())?;
+    module.define_singleton_method(
+        "callback_error_handler",
+        magnus::method!(callback_error_handler, 0),
+    )?;
+    module.define_singleton_method(
+        "callback_error_handler=",
+        magnus::method!(set_callback_error_handler, 1),
+    )?;
+    module.define_singleton_method(
+        "set_callback_timeout",
+        magnus::method!(set_callback_timeout, 2),
+    )?;
+    module.define_singleton_method(
+        "get_callback_timeout",
+        magnus::method!(get_callback_timeout, 1),
+    )?;
+    module.define_singleton_method("callback_stats", magnus::method!(callback_stats, 0))?;
+    module.define_singleton_method(
+        "reset_callback_stats",
+        magnus::method!(reset_callback_stats, 0),
+    )?;
+    module.define_singleton_method("shutdown", magnus::method!(shutdown, 0))?;
+    module.define_singleton_method("__after_fork", magnus::method!(after_fork, 0))?;
+    let _ = MODULE.set(module.into());
+
+    start(&ruby)
 }
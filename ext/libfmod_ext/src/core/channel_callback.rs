@@ -3,7 +3,7 @@
 // This Source Code Form is subject to the terms of the Mozilla Public
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
-use crate::{callback, IntoRuby, Result};
+use crate::{callback, listeners, IntoRuby, Result};
 use magnus::{
     prelude::*,
     value::{InnerValue, Opaque},
@@ -14,6 +14,16 @@ use super::channel_control::RbChannelControl;
 
 static CLASS: OnceCell<Opaque<magnus::RClass>> = OnceCell::new();
 
+/// Events that can be listened to with `ChannelControl#on`.
+///
+/// Channel callbacks don't take a mask, so the bits here are only used to tell events apart.
+pub const EVENTS: listeners::Events = &[
+    ("end", 1 << 0),
+    ("virtual_voice", 1 << 1),
+    ("sync_point", 1 << 2),
+    ("occlusion", 1 << 3),
+];
+
 pub struct ChannelControlCallback;
 
 impl IntoRuby<RbChannelControl> for fmod::ChannelControlType {
@@ -26,51 +36,63 @@ impl IntoRuby<RbChannelControl> for fmod::ChannelControlType {
     }
 }
 
+fn system_queue(channel_control: fmod::ChannelControlType) -> Option<callback::Queue> {
+    let system = match channel_control {
+        fmod::ChannelControlType::Channel(c) => c.get_system(),
+        fmod::ChannelControlType::ChannelGroup(c) => c.get_system(),
+    };
+    system.ok().map(callback::Queue::System)
+}
+
 impl fmod::ChannelControlCallback for ChannelControlCallback {
     fn end(channel_control: fmod::ChannelControlType) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let channel_control = channel_control.into_ruby().unwrap();
-            let callback: magnus::RObject = channel_control.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback.funcall("end", (channel_control,)).unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            system_queue(channel_control),
+            "end",
+            &channel_control,
+            move |_| {
+                let channel_control = channel_control.into_ruby()?;
+                let callback: magnus::Value = channel_control.ivar_get("__callback")?;
+                let _: magnus::Value = listeners::dispatch(callback, "end", (channel_control,))?;
+                Ok(())
+            },
+        )
     }
 
     fn virtual_voice(
         channel_control: fmod::ChannelControlType,
         is_virtual: bool,
     ) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let channel_control = channel_control.into_ruby().unwrap();
-            let callback: magnus::RObject = channel_control.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback
-                .funcall("virtual_voice", (channel_control, is_virtual))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            system_queue(channel_control),
+            "virtual_voice",
+            &channel_control,
+            move |_| {
+                let channel_control = channel_control.into_ruby()?;
+                let callback: magnus::Value = channel_control.ivar_get("__callback")?;
+                let _: magnus::Value =
+                    listeners::dispatch(callback, "virtual_voice", (channel_control, is_virtual))?;
+                Ok(())
+            },
+        )
     }
 
     fn sync_point(
         channel_control: fmod::ChannelControlType,
         sync_point: std::ffi::c_int,
     ) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let channel_control = channel_control.into_ruby().unwrap();
-            let callback: magnus::RObject = channel_control.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback
-                .funcall("sync_point", (channel_control, sync_point))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            system_queue(channel_control),
+            "sync_point",
+            &channel_control,
+            move |_| {
+                let channel_control = channel_control.into_ruby()?;
+                let callback: magnus::Value = channel_control.ivar_get("__callback")?;
+                let _: magnus::Value =
+                    listeners::dispatch(callback, "sync_point", (channel_control, sync_point))?;
+                Ok(())
+            },
+        )
     }
 
     fn occlusion(
@@ -78,15 +100,13 @@ impl fmod::ChannelControlCallback for ChannelControlCallback {
         direct: &mut std::ffi::c_float,
         reverb: &mut std::ffi::c_float,
     ) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel();
-        callback::process(move |_| {
-            let channel_control = channel_control.into_ruby().unwrap();
-            let callback: magnus::RObject = channel_control.ivar_get("__callback").unwrap();
+        let result = callback::call("occlusion", &channel_control, move |_| {
+            let channel_control = channel_control.into_ruby()?;
+            let callback: magnus::Value = channel_control.ivar_get("__callback")?;
             let result: Option<(f32, f32)> =
-                callback.funcall("occlusion", (channel_control,)).unwrap();
-            sender.send(result).unwrap();
-        });
-        let result = reciever.recv().unwrap();
+                listeners::dispatch(callback, "occlusion", (channel_control,))?;
+            Ok(result)
+        })?;
         if let Some((d, r)) = result {
             *direct = d;
             *reverb = r;
@@ -14,12 +14,18 @@ use super::{
     system::RbSystem,
 };
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-#[magnus::wrap(class = "FMOD::ChannelControl", size, free_immediately)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, magnus::TypedData)]
+#[magnus(class = "FMOD::ChannelControl", size, free_immediately)]
 pub struct ChannelControl(
     pub(super) fmod::ChannelControl,
     pub(super) ChannelControlType,
 );
+
+impl magnus::DataTypeFunctions for ChannelControl {
+    fn free(self: Box<Self>) {
+        crate::extern_struct_storage::finalize(self.0, &*self);
+    }
+}
 pub type RbChannelControl = Obj<ChannelControl>;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -95,6 +101,48 @@ impl ChannelControl {
         rb_self.ivar_set("__callback", callback)?;
         control.set_callback::<ChannelControlCallback>().into_ruby()
     }
+
+    // channel callbacks don't have a mask, so there's nothing to do beyond making sure the callback is set
+    fn apply_callback_mask(rb_self: magnus::Value, _: u32) -> Result<()> {
+        let rb_self: Obj<Self> = magnus::TryConvert::try_convert(rb_self)?;
+        let control: fmod::ChannelControl = rb_self.from_ruby()?;
+        control.set_callback::<ChannelControlCallback>().into_ruby()
+    }
+
+    fn subscribe(rb_self: Obj<Self>, event: magnus::Symbol) -> Result<magnus::Value> {
+        let ruby = magnus::Ruby::get().unwrap();
+        crate::listeners::subscribe(
+            rb_self,
+            event,
+            ruby.block_proc()?,
+            super::channel_callback::EVENTS,
+            Self::apply_callback_mask,
+        )
+    }
+
+    fn on(rb_self: Obj<Self>, event: magnus::Symbol) -> Result<magnus::Value> {
+        Self::subscribe(rb_self, event)
+    }
+
+    fn on_end(rb_self: Obj<Self>) -> Result<magnus::Value> {
+        let ruby = magnus::Ruby::get().unwrap();
+        Self::subscribe(rb_self, ruby.to_symbol("end"))
+    }
+
+    fn on_virtual_voice(rb_self: Obj<Self>) -> Result<magnus::Value> {
+        let ruby = magnus::Ruby::get().unwrap();
+        Self::subscribe(rb_self, ruby.to_symbol("virtual_voice"))
+    }
+
+    fn on_sync_point(rb_self: Obj<Self>) -> Result<magnus::Value> {
+        let ruby = magnus::Ruby::get().unwrap();
+        Self::subscribe(rb_self, ruby.to_symbol("sync_point"))
+    }
+
+    fn on_occlusion(rb_self: Obj<Self>) -> Result<magnus::Value> {
+        let ruby = magnus::Ruby::get().unwrap();
+        Self::subscribe(rb_self, ruby.to_symbol("occlusion"))
+    }
 }
 
 extern_struct_fns! {
@@ -158,6 +206,11 @@ extern_struct_fns! {
 extern_struct_bind! {
   impl Bindable for ChannelControl: fmod::ChannelControl {
     fn set_callback -> 1;
+    fn on -> 1;
+    fn on_end -> 0;
+    fn on_virtual_voice -> 0;
+    fn on_sync_point -> 0;
+    fn on_occlusion -> 0;
     fn add_dsp -> 2;
     fn remove_dsp -> 1;
     fn get_dsp_count -> 0;
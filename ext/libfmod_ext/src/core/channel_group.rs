@@ -46,6 +46,17 @@ impl ChannelGroup {
         crate::extern_struct_storage::remove(*group);
         group.release().into_ruby()
     }
+
+    fn set_owned_by_ruby(rb_self: RbChannelGroup, owned: bool) -> Result<()> {
+        let group: fmod::ChannelGroup = rb_self.from_ruby()?;
+        crate::extern_struct_storage::set_owned(*group, rb_self, owned);
+        Ok(())
+    }
+
+    fn is_owned_by_ruby(rb_self: RbChannelGroup) -> Result<bool> {
+        let group: fmod::ChannelGroup = rb_self.from_ruby()?;
+        Ok(crate::extern_struct_storage::is_owned(*group))
+    }
 }
 
 extern_struct_fns! {
@@ -66,6 +77,8 @@ extern_struct_bind! {
     fn get_channel -> 1;
     fn get_name -> 0;
     fn release -> 0;
+    fn set_owned_by_ruby -> 1;
+    fn is_owned_by_ruby -> 0;
     fn add_group -> 2;
     fn get_group_count -> 0;
     fn get_group -> 1;
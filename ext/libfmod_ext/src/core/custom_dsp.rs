@@ -0,0 +1,651 @@
+// Copyright (c) 2024 Lily Lyons
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+use std::{
+    cell::UnsafeCell,
+    ffi::{c_char, c_int, c_uint, CString},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex, Weak,
+    },
+};
+
+use fmod::ffi::{
+    FMOD_DSP_DESCRIPTION, FMOD_DSP_PARAMETER_DESC, FMOD_DSP_PARAMETER_DESC_FLOAT,
+    FMOD_DSP_PARAMETER_TYPE, FMOD_DSP_STATE, FMOD_RESULT,
+};
+use magnus::{
+    prelude::*,
+    value::{InnerValue, Opaque},
+};
+use once_cell::sync::Lazy;
+
+use crate::{callback, error::check, thread, FromRuby, IntoRuby, Result};
+
+use super::dsp::RbDSP;
+use super::system::RbSystem;
+
+/// What a custom DSP does to its audio.
+enum Processor {
+    /// A ruby block, called on the callback thread for every mix block. FMOD's mixer waits for it,
+    /// so anything slow (or the GVL being held elsewhere) is heard as dropouts. With a `dsp_read` callback timeout
+    /// set, the audio passes through untouched until ruby catches up.
+    Block(Opaque<magnus::block::Proc>),
+    /// Reduces the bit depth and sample rate.
+    Bitcrusher,
+    /// Multiplies the signal with a sine wave.
+    RingModulator,
+    /// Holds the signal at its value every so often, for a stepped sound.
+    SampleAndHold,
+}
+
+struct Parameter {
+    name: String,
+    label: String,
+    description: String,
+    min: f32,
+    max: f32,
+    default: f32,
+}
+
+impl Parameter {
+    fn new(
+        name: &str,
+        label: &str,
+        description: &str,
+        (min, max, default): (f32, f32, f32),
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            label: label.to_string(),
+            description: description.to_string(),
+            min,
+            max,
+            default,
+        }
+    }
+}
+
+impl Processor {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bitcrusher" => Some(Self::Bitcrusher),
+            "ring_modulator" => Some(Self::RingModulator),
+            "sample_and_hold" => Some(Self::SampleAndHold),
+            _ => None,
+        }
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        match self {
+            Self::Block(_) => vec![],
+            Self::Bitcrusher => vec![
+                Parameter::new(
+                    "Bits",
+                    "bits",
+                    "Bit depth the signal is reduced to",
+                    (1.0, 24.0, 8.0),
+                ),
+                Parameter::new(
+                    "Downsample",
+                    "x",
+                    "How many samples each sample is held for",
+                    (1.0, 64.0, 1.0),
+                ),
+            ],
+            Self::RingModulator => vec![
+                Parameter::new(
+                    "Frequency",
+                    "Hz",
+                    "Frequency of the modulating sine wave",
+                    (0.1, 20000.0, 440.0),
+                ),
+                Parameter::new(
+                    "Mix",
+                    "",
+                    "How much of the modulated signal is heard",
+                    (0.0, 1.0, 1.0),
+                ),
+            ],
+            Self::SampleAndHold => vec![Parameter::new(
+                "Rate",
+                "Hz",
+                "How often a new value is held",
+                (1.0, 48000.0, 100.0),
+            )],
+        }
+    }
+}
+
+// what the native processors remember between mix blocks
+#[derive(Default)]
+struct State {
+    held: Vec<f32>,
+    counter: u32,
+    phase: f64,
+}
+
+// what a ruby block is called with, kept around so the mixer thread doesn't allocate for every mix block
+#[derive(Default)]
+struct BlockBuffers {
+    samples: Vec<u8>,
+    parameters: Vec<f32>,
+}
+
+// one of the two buffers a ruby block is handed. whoever set `busy` has the buffers to themselves
+#[derive(Default)]
+struct Slot {
+    buffers: UnsafeCell<BlockBuffers>,
+    busy: AtomicBool,
+}
+
+// the buffers are only touched by whoever claimed the slot
+unsafe impl Sync for Slot {}
+
+// a claimed slot, given back when the callback it's moved into is dropped, whether it ran or was abandoned after a timeout
+struct Claim {
+    slots: Arc<[Slot; 2]>,
+    index: usize,
+}
+
+impl Claim {
+    fn take(slots: &Arc<[Slot; 2]>) -> Option<Self> {
+        let index = slots.iter().position(|slot| {
+            slot.busy
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        })?;
+        Some(Self {
+            slots: slots.clone(),
+            index,
+        })
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn buffers(&self) -> &mut BlockBuffers {
+        // nothing else touches a slot while it's claimed
+        unsafe { &mut *self.slots[self.index].buffers.get() }
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        self.slots[self.index].busy.store(false, Ordering::Release);
+    }
+}
+
+/// A DSP created with `System#create_custom_dsp`.
+pub struct CustomDsp {
+    name: String,
+    processor: Processor,
+    // f32 bits, so they can be set from ruby while FMOD's mixer reads them
+    values: Box<[AtomicU32]>,
+    // only FMOD's mixer thread calls `read`, so nothing else touches this
+    state: UnsafeCell<State>,
+    // a call abandoned after a timeout keeps its slot until ruby is done with it, the next mix block takes the other
+    slots: Arc<[Slot; 2]>,
+    // everything FMOD was pointed at, which has to stay put for as long as the DSP exists
+    _parameter_descs: Box<[FMOD_DSP_PARAMETER_DESC]>,
+    parameter_desc_ptrs: Box<[*mut FMOD_DSP_PARAMETER_DESC]>,
+    _strings: Box<[CString]>,
+    description: FMOD_DSP_DESCRIPTION,
+}
+
+// the raw pointers only point into the struct itself
+unsafe impl Send for CustomDsp {}
+unsafe impl Sync for CustomDsp {}
+
+impl std::fmt::Debug for CustomDsp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+// every live custom DSP, so their blocks can be marked
+static CUSTOM_DSPS: Lazy<Mutex<Vec<Weak<CustomDsp>>>> = Lazy::new(Default::default);
+
+#[derive(magnus::TypedData)]
+#[magnus(class = "FMOD::CustomDSP", mark)]
+struct _CustomDsp;
+
+impl magnus::DataTypeFunctions for _CustomDsp {
+    fn mark(&self, marker: &magnus::gc::Marker) {
+        let ruby = magnus::Ruby::get().unwrap();
+        for dsp in CUSTOM_DSPS.lock().unwrap().iter().filter_map(Weak::upgrade) {
+            if let Processor::Block(block) = &dsp.processor {
+                marker.mark(block.get_inner_with(&ruby));
+            }
+        }
+    }
+}
+
+fn copy_str<const N: usize>(dest: &mut [c_char; N], src: &str) {
+    // leave room for the nul terminator, the rest is already zeroed
+    for (dest, byte) in dest.iter_mut().zip(src.bytes().take(N - 1)) {
+        *dest = byte as c_char;
+    }
+}
+
+fn c_string(s: &str) -> CString {
+    CString::new(s.replace('\0', "")).unwrap()
+}
+
+impl CustomDsp {
+    fn new(name: String, processor: Processor, parameters: Vec<Parameter>) -> Arc<Self> {
+        let mut strings = Vec::with_capacity(parameters.len());
+        let parameter_descs: Box<[FMOD_DSP_PARAMETER_DESC]> = parameters
+            .iter()
+            .map(|parameter| {
+                let mut desc: FMOD_DSP_PARAMETER_DESC = unsafe { std::mem::zeroed() };
+                desc.type_ = FMOD_DSP_PARAMETER_TYPE::FMOD_DSP_PARAMETER_TYPE_FLOAT;
+                copy_str(&mut desc.name, &parameter.name);
+                copy_str(&mut desc.label, &parameter.label);
+                // the CString's buffer doesn't move when the CString does
+                let description = c_string(&parameter.description);
+                desc.description = description.as_ptr();
+                strings.push(description);
+                desc.__bindgen_anon_1.floatdesc = FMOD_DSP_PARAMETER_DESC_FLOAT {
+                    min: parameter.min,
+                    max: parameter.max,
+                    defaultval: parameter.default,
+                    // zeroed is FMOD_DSP_PARAMETER_FLOAT_MAPPING_TYPE_AUTO
+                    mapping: unsafe { std::mem::zeroed() },
+                };
+                desc
+            })
+            .collect();
+        let parameter_desc_ptrs: Box<[*mut FMOD_DSP_PARAMETER_DESC]> = parameter_descs
+            .iter()
+            .map(|desc| std::ptr::from_ref(desc).cast_mut())
+            .collect();
+        let values = parameters
+            .iter()
+            .map(|parameter| AtomicU32::new(parameter.default.to_bits()))
+            .collect();
+
+        let dsp = Arc::new_cyclic(|this: &Weak<CustomDsp>| {
+            let mut description: FMOD_DSP_DESCRIPTION = unsafe { std::mem::zeroed() };
+            description.pluginsdkversion = fmod::ffi::FMOD_PLUGIN_SDK_VERSION;
+            copy_str(&mut description.name, &name);
+            description.version = 1;
+            description.numinputbuffers = 1;
+            description.numoutputbuffers = 1;
+            description.create = Some(create);
+            description.read = Some(read);
+            description.numparameters = parameter_desc_ptrs.len() as c_int;
+            description.paramdesc = if parameter_desc_ptrs.is_empty() {
+                std::ptr::null_mut()
+            } else {
+                parameter_desc_ptrs.as_ptr().cast_mut()
+            };
+            description.setparameterfloat = Some(set_parameter_float);
+            description.getparameterfloat = Some(get_parameter_float);
+            // only read by `create`, which moves it to the DSP's plugin data
+            description.userdata = this.as_ptr().cast_mut().cast();
+
+            CustomDsp {
+                name,
+                processor,
+                values,
+                state: UnsafeCell::default(),
+                slots: Arc::default(),
+                _parameter_descs: parameter_descs,
+                parameter_desc_ptrs,
+                _strings: strings.into(),
+                description,
+            }
+        });
+
+        let mut dsps = CUSTOM_DSPS.lock().unwrap();
+        dsps.retain(|dsp| dsp.strong_count() > 0);
+        dsps.push(Arc::downgrade(&dsp));
+        dsp
+    }
+
+    fn value(&self, index: usize) -> f32 {
+        f32::from_bits(self.values[index].load(Ordering::Relaxed))
+    }
+
+    // `buffer` holds the input, and is processed in place
+    fn process(&self, buffer: &mut [f32], channels: usize, sample_rate: f64) {
+        if channels == 0 {
+            return;
+        }
+        if let Processor::Block(block) = &self.processor {
+            self.process_block(*block, buffer, channels);
+            return;
+        }
+        // only called from the mixer thread
+        let state = unsafe { &mut *self.state.get() };
+
+        match &self.processor {
+            Processor::Bitcrusher => {
+                let levels = 2f32.powf(self.value(0).clamp(1.0, 24.0) - 1.0);
+                let hold = self.value(1).max(1.0) as u32;
+                state.held.resize(channels, 0.0);
+                for frame in buffer.chunks_exact_mut(channels) {
+                    if state.counter == 0 {
+                        for (held, sample) in state.held.iter_mut().zip(frame.iter()) {
+                            *held = (sample * levels).round() / levels;
+                        }
+                    }
+                    frame.copy_from_slice(&state.held);
+                    state.counter = (state.counter + 1) % hold;
+                }
+            }
+            Processor::RingModulator => {
+                let step = f64::from(self.value(0)) / sample_rate;
+                let mix = self.value(1);
+                for frame in buffer.chunks_exact_mut(channels) {
+                    let modulator = (state.phase * std::f64::consts::TAU).sin() as f32;
+                    for sample in frame {
+                        *sample *= 1.0 - mix + modulator * mix;
+                    }
+                    state.phase = (state.phase + step).fract();
+                }
+            }
+            Processor::SampleAndHold => {
+                let step = f64::from(self.value(0)) / sample_rate;
+                if state.held.len() != channels {
+                    state.held.resize(channels, 0.0);
+                    // take the first sample straight away
+                    state.phase = 1.0;
+                }
+                for frame in buffer.chunks_exact_mut(channels) {
+                    if state.phase >= 1.0 {
+                        state.phase = state.phase.fract();
+                        state.held.copy_from_slice(frame);
+                    }
+                    frame.copy_from_slice(&state.held);
+                    state.phase += step;
+                }
+            }
+            Processor::Block(_) => unreachable!("handled by process_block"),
+        }
+    }
+
+    fn process_block(
+        &self,
+        block: Opaque<magnus::block::Proc>,
+        buffer: &mut [f32],
+        channels: usize,
+    ) {
+        // if both slots are still waiting for ruby, queueing another call behind them would only make ruby fall
+        // further behind. the audio passes through untouched instead
+        let Some(claim) = Claim::take(&self.slots) else {
+            return;
+        };
+        {
+            let buffers = claim.buffers();
+            buffers.samples.clear();
+            buffers
+                .samples
+                .extend(buffer.iter().flat_map(|s| s.to_ne_bytes()));
+            buffers.parameters.clear();
+            buffers
+                .parameters
+                .extend((0..self.values.len()).map(|i| self.value(i)));
+        }
+        let slots = self.slots.clone();
+        let index = claim.index;
+        let length = buffer.len() / channels;
+
+        let processed = callback::call("dsp_read", self, move |ruby| {
+            let buffers = claim.buffers();
+            let in_buf = ruby.str_from_slice(&buffers.samples);
+            in_buf.freeze();
+            let out_buf = ruby.str_from_slice(&buffers.samples);
+            let parameters = ruby.ary_from_iter(buffers.parameters.iter().copied());
+            let _: magnus::Value = block
+                .get_inner_with(ruby)
+                .call((in_buf, out_buf, length, channels, parameters))?;
+
+            // copy back while we still have the GVL
+            let output = unsafe { out_buf.as_slice() };
+            let len = output.len().min(buffers.samples.len());
+            buffers.samples[..len].copy_from_slice(&output[..len]);
+            Ok(true)
+        });
+        // if the block failed (or took too long) the audio passes through untouched
+        if let Ok(true) = processed {
+            // the callback (and its claim) was dropped before it answered, and only the mixer thread claims slots
+            let slot = &slots[index];
+            debug_assert!(!slot.busy.load(Ordering::Acquire));
+            let buffers = unsafe { &*slot.buffers.get() };
+            for (sample, bytes) in buffer.iter_mut().zip(buffers.samples.chunks_exact(4)) {
+                *sample = f32::from_ne_bytes(bytes.try_into().unwrap());
+            }
+        }
+    }
+}
+
+unsafe fn custom_dsp<'a>(dsp_state: *mut FMOD_DSP_STATE) -> Option<&'a CustomDsp> {
+    let data = unsafe { (*dsp_state).plugindata };
+    // the DSP's storage entry keeps this alive until after the DSP is released
+    unsafe { data.cast::<CustomDsp>().as_ref() }
+}
+
+unsafe extern "C" fn create(dsp_state: *mut FMOD_DSP_STATE) -> FMOD_RESULT {
+    let functions = unsafe { &*(*dsp_state).functions };
+    let Some(get_userdata) = functions.getuserdata else {
+        return FMOD_RESULT::FMOD_ERR_INTERNAL;
+    };
+    // the DSP's userdata is taken over by the storage once it's wrapped, so keep our own copy
+    let mut userdata = std::ptr::null_mut();
+    let result = unsafe { get_userdata(dsp_state, &mut userdata) };
+    unsafe { (*dsp_state).plugindata = userdata };
+    result
+}
+
+unsafe extern "C" fn read(
+    dsp_state: *mut FMOD_DSP_STATE,
+    inbuffer: *mut f32,
+    outbuffer: *mut f32,
+    length: c_uint,
+    inchannels: c_int,
+    outchannels: *mut c_int,
+) -> FMOD_RESULT {
+    let Some(dsp) = (unsafe { custom_dsp(dsp_state) }) else {
+        return FMOD_RESULT::FMOD_ERR_INVALID_HANDLE;
+    };
+    let length = length as usize;
+    let in_channels = inchannels.max(0) as usize;
+    let out_channels = unsafe { *outchannels }.max(0) as usize;
+    let input = unsafe { std::slice::from_raw_parts(inbuffer, length * in_channels) };
+    let output = unsafe { std::slice::from_raw_parts_mut(outbuffer, length * out_channels) };
+
+    // start from the input, wrapping around if there are more output channels than input channels
+    for (frame, out) in output.chunks_exact_mut(out_channels.max(1)).enumerate() {
+        for (channel, sample) in out.iter_mut().enumerate() {
+            *sample = match in_channels {
+                0 => 0.0,
+                _ => input[frame * in_channels + channel % in_channels],
+            };
+        }
+    }
+
+    let functions = unsafe { &*(*dsp_state).functions };
+    let mut sample_rate = 48000;
+    if let Some(get_sample_rate) = functions.getsamplerate {
+        unsafe { get_sample_rate(dsp_state, &mut sample_rate) };
+    }
+    dsp.process(output, out_channels, f64::from(sample_rate.max(1)));
+    FMOD_RESULT::FMOD_OK
+}
+
+unsafe extern "C" fn set_parameter_float(
+    dsp_state: *mut FMOD_DSP_STATE,
+    index: c_int,
+    value: f32,
+) -> FMOD_RESULT {
+    let Some(dsp) = (unsafe { custom_dsp(dsp_state) }) else {
+        return FMOD_RESULT::FMOD_ERR_INVALID_HANDLE;
+    };
+    match dsp.values.get(index as usize) {
+        Some(slot) => {
+            slot.store(value.to_bits(), Ordering::Relaxed);
+            FMOD_RESULT::FMOD_OK
+        }
+        None => FMOD_RESULT::FMOD_ERR_INVALID_PARAM,
+    }
+}
+
+unsafe extern "C" fn get_parameter_float(
+    dsp_state: *mut FMOD_DSP_STATE,
+    index: c_int,
+    value: *mut f32,
+    valuestr: *mut c_char,
+) -> FMOD_RESULT {
+    let Some(dsp) = (unsafe { custom_dsp(dsp_state) }) else {
+        return FMOD_RESULT::FMOD_ERR_INVALID_HANDLE;
+    };
+    if index < 0 || index as usize >= dsp.values.len() {
+        return FMOD_RESULT::FMOD_ERR_INVALID_PARAM;
+    }
+    unsafe {
+        if !value.is_null() {
+            *value = dsp.value(index as usize);
+        }
+        if !valuestr.is_null() {
+            *valuestr = 0;
+        }
+    }
+    FMOD_RESULT::FMOD_OK
+}
+
+fn parse_parameter(parameter: magnus::RHash) -> Result<Parameter> {
+    let kwargs = magnus::scan_args::get_kwargs::<
+        _,
+        (String,),
+        (
+            Option<f32>,
+            Option<f32>,
+            Option<f32>,
+            Option<String>,
+            Option<String>,
+        ),
+        (),
+    >(
+        parameter,
+        &["name"],
+        &["min", "max", "default", "label", "description"],
+    )?;
+    let (name,) = kwargs.required;
+    let (min, max, default, label, description) = kwargs.optional;
+    let (min, max) = (min.unwrap_or(0.0), max.unwrap_or(1.0));
+    if min > max {
+        return Err(magnus::Error::new(
+            magnus::exception::arg_error(),
+            format!("parameter {name} has a min greater than its max"),
+        ));
+    }
+    Ok(Parameter {
+        label: label.unwrap_or_default(),
+        description: description.unwrap_or_default(),
+        default: default.unwrap_or(min).clamp(min, max),
+        name,
+        min,
+        max,
+    })
+}
+
+// create_custom_dsp(name:, channels: nil, parameters: [], processor: nil) { |in_buf, out_buf, length, channels, parameters| ... }
+pub fn create(rb_system: RbSystem, args: &[magnus::Value]) -> Result<RbDSP> {
+    let system: fmod::System = rb_system.from_ruby()?;
+    let args =
+        magnus::scan_args::scan_args::<(), (), (), (), magnus::RHash, Option<magnus::block::Proc>>(
+            args,
+        )?;
+    let kwargs = magnus::scan_args::get_kwargs::<
+        _,
+        (String,),
+        (Option<i32>, Option<magnus::RArray>, Option<magnus::Symbol>),
+        (),
+    >(
+        args.keywords,
+        &["name"],
+        &["channels", "parameters", "processor"],
+    )?;
+    let (name,) = kwargs.required;
+    let (channels, parameters, processor) = kwargs.optional;
+
+    let (processor, parameters) = match (processor, args.block) {
+        (Some(processor), None) => {
+            let processor_name = processor.name()?;
+            let processor = Processor::from_name(&processor_name).ok_or_else(|| {
+                magnus::Error::new(
+                    magnus::exception::arg_error(),
+                    format!("unknown processor :{processor_name}"),
+                )
+            })?;
+            if parameters.is_some() {
+                return Err(magnus::Error::new(
+                    magnus::exception::arg_error(),
+                    "native processors have their own parameters",
+                ));
+            }
+            let parameters = processor.parameters();
+            (processor, parameters)
+        }
+        (None, Some(block)) => {
+            let parameters = match parameters {
+                Some(parameters) => parameters
+                    .into_iter()
+                    .map(|parameter| parse_parameter(magnus::RHash::try_convert(parameter)?))
+                    .collect::<Result<Vec<_>>>()?,
+                None => vec![],
+            };
+            (Processor::Block(block.into()), parameters)
+        }
+        _ => {
+            return Err(magnus::Error::new(
+                magnus::exception::arg_error(),
+                "create_custom_dsp takes either a processor: or a block",
+            ))
+        }
+    };
+
+    let custom = CustomDsp::new(name, processor, parameters);
+    let mut raw = std::ptr::null_mut();
+    let result =
+        unsafe { fmod::ffi::FMOD_System_CreateDSP(system.into(), &custom.description, &mut raw) };
+    check(result).into_ruby()?;
+    let dsp = fmod::Dsp::from(raw);
+
+    if let Some(channels) = channels {
+        let result = unsafe {
+            fmod::ffi::FMOD_DSP_SetChannelFormat(
+                raw,
+                0,
+                channels,
+                fmod::ffi::FMOD_SPEAKERMODE::FMOD_SPEAKERMODE_DEFAULT,
+            )
+        };
+        if let Err(error) = check(result) {
+            let _ = unsafe { thread::without_gvl_no_ubf(|| dsp.release()) };
+            return Err(crate::error::from_fmod(error));
+        }
+    }
+
+    let _scope = crate::extern_struct_storage::OwnerScope::enter(system);
+    let rb_dsp: RbDSP = match dsp.into_ruby() {
+        Ok(rb_dsp) => rb_dsp,
+        Err(error) => {
+            // `custom` is dropped on return, so FMOD can't be left holding on to it
+            let _ = unsafe { thread::without_gvl_no_ubf(|| dsp.release()) };
+            return Err(error);
+        }
+    };
+    // FMOD calls into this until the DSP is released
+    crate::extern_struct_storage::attach(dsp, custom);
+    Ok(rb_dsp)
+}
+
+pub fn bind(module: magnus::RModule) -> Result<()> {
+    module.define_class("CustomDSP", magnus::class::basic_object())?;
+    module.ivar_set("__custom_dsp", _CustomDsp)?;
+
+    Ok(())
+}
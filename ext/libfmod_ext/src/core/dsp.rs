@@ -4,20 +4,42 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 #![allow(clippy::upper_case_acronyms)]
-use magnus::prelude::*;
+use std::{collections::HashMap, sync::Mutex};
 
-use crate::{Bindable, FromRuby, IntoRuby, Result};
+use magnus::{
+    prelude::*,
+    value::{InnerValue, Opaque},
+};
+use once_cell::sync::Lazy;
+
+use crate::{error::check, Bindable, FromRuby, IntoRuby, Result};
 
 use crate::{extern_struct, extern_struct_bind, extern_struct_fns};
 
 use super::dsp_connection::RbDSPConnection;
 use super::enums::{DspConnectionType, DspParameterDataType, DspType, SpeakerMode};
 use super::flags::ChannelMask;
-use super::structs::DspMeteringInfo;
+use super::structs::{DspMeteringInfo, DspParameterInfo};
 use super::system::RbSystem;
 
 extern_struct! {
-  struct DSP: fmod::Dsp => "FMOD::DSP"
+  struct DSP: fmod::Dsp => "FMOD::DSP", class = class_for
+}
+
+// the subclass of FMOD::DSP used for each DSP type, registered with `FMOD::DSP.register_type`.
+// the classes are also kept in FMOD::DSP::CLASSES, so they're never collected
+static CLASSES: Lazy<Mutex<HashMap<DspType, Opaque<magnus::RClass>>>> = Lazy::new(Default::default);
+
+fn class_for(dsp: fmod::Dsp) -> magnus::RClass {
+    let ruby = magnus::Ruby::get().unwrap();
+    let class = dsp.get_type().ok().and_then(|kind| {
+        let kind: DspType = kind.into();
+        CLASSES.lock().unwrap().get(&kind).copied()
+    });
+    match class {
+        Some(class) => class.get_inner_with(&ruby),
+        None => <DSP as magnus::TypedData>::class(&ruby),
+    }
 }
 
 impl DSP {
@@ -25,8 +47,83 @@ impl DSP {
         use crate::{FromRuby, IntoRuby};
         // we dont need to check if the dsp is already removed, because FromRuby will return an error if it is
         let dsp: fmod::Dsp = rb_self.from_ruby()?;
+        // a custom DSP's mixer thread may be waiting on ruby, and FMOD waits for the mixer here
+        let result = unsafe { crate::thread::without_gvl_no_ubf(|| dsp.release()) };
+        // removed afterwards, so anything attached to the dsp (like a custom dsp's state) outlives FMOD's use of it
         crate::extern_struct_storage::remove(dsp);
-        dsp.release().into_ruby()
+        result.into_ruby()
+    }
+
+    fn set_owned_by_ruby(rb_self: RbDSP, owned: bool) -> Result<()> {
+        let dsp: fmod::Dsp = rb_self.from_ruby()?;
+        crate::extern_struct_storage::set_owned(dsp, rb_self, owned);
+        Ok(())
+    }
+
+    fn is_owned_by_ruby(rb_self: RbDSP) -> Result<bool> {
+        let dsp: fmod::Dsp = rb_self.from_ruby()?;
+        Ok(crate::extern_struct_storage::is_owned(dsp))
+    }
+
+    // DSPs of `kind` are wrapped as `class` from now on, which has to be a subclass of FMOD::DSP
+    fn register_type(kind: DspType, class: magnus::RClass) -> Result<()> {
+        let ruby = magnus::Ruby::get().unwrap();
+        let base = <DSP as magnus::TypedData>::class(&ruby);
+        let is_subclass: Option<bool> = class.funcall("<=", (base,))?;
+        if is_subclass != Some(true) {
+            return Err(magnus::Error::new(
+                magnus::exception::arg_error(),
+                format!("{} is not a subclass of FMOD::DSP", class.inspect()),
+            ));
+        }
+
+        let classes: magnus::RHash = base.const_get("CLASSES")?;
+        classes.aset(kind, class)?;
+        CLASSES.lock().unwrap().insert(kind, class.into());
+        Ok(())
+    }
+
+    // decodes a data parameter into ruby objects. user data parameters are returned as a binary string
+    fn get_parameter_data_value(rb_self: RbDSP, index: i32) -> Result<magnus::Value> {
+        use fmod::ffi::{FMOD_DSP_PARAMETER_DESC, FMOD_DSP_PARAMETER_TYPE};
+
+        let ruby = magnus::Ruby::get().unwrap();
+        let dsp: fmod::Dsp = rb_self.from_ruby()?;
+        let raw: *mut fmod::ffi::FMOD_DSP = dsp.into();
+
+        let mut desc: *mut FMOD_DSP_PARAMETER_DESC = std::ptr::null_mut();
+        check(unsafe { fmod::ffi::FMOD_DSP_GetParameterInfo(raw, index, &mut desc) })
+            .into_ruby()?;
+        let desc = unsafe { &*desc };
+        if desc.type_ != FMOD_DSP_PARAMETER_TYPE::FMOD_DSP_PARAMETER_TYPE_DATA {
+            return Err(magnus::Error::new(
+                magnus::exception::arg_error(),
+                format!("parameter {index} is not a data parameter"),
+            ));
+        }
+        let data_type = unsafe { desc.__bindgen_anon_1.datadesc.datatype };
+
+        let mut data = std::ptr::null_mut();
+        let mut length = 0;
+        check(unsafe {
+            fmod::ffi::FMOD_DSP_GetParameterData(
+                raw,
+                index,
+                &mut data,
+                &mut length,
+                std::ptr::null_mut(),
+                0,
+            )
+        })
+        .into_ruby()?;
+        let bytes = unsafe { std::slice::from_raw_parts(data.cast::<u8>(), length as usize) };
+
+        // anything positive is a user data type, which we can't know the layout of
+        if data_type >= 0 {
+            return Ok(ruby.str_from_slice(bytes).as_value());
+        }
+        let data_type: fmod::DspParameterDataType = data_type.from_ruby()?;
+        super::dsp_data::decode(&ruby, data_type, bytes)
     }
 
     fn get_userdata(rb_self: RbDSP) -> Result<magnus::Value> {
@@ -37,6 +134,15 @@ impl DSP {
         rb_self.ivar_set("__userdata", data)
     }
 
+    fn get_parameter_info(rb_self: RbDSP, index: i32) -> Result<DspParameterInfo> {
+        let dsp: fmod::Dsp = rb_self.from_ruby()?;
+        let mut desc = std::ptr::null_mut();
+        let result = unsafe { fmod::ffi::FMOD_DSP_GetParameterInfo(dsp.into(), index, &mut desc) };
+        check(result).into_ruby()?;
+        // the description is owned by the DSP, so it's copied into ruby right away
+        unsafe { &*desc }.into_ruby()
+    }
+
     fn set_parameter_data(rb_self: RbDSP, index: i32, data: magnus::RString) -> Result<()> {
         let dsp = rb_self.from_ruby()?;
         let data = data.from_ruby()?;
@@ -65,7 +171,7 @@ extern_struct_fns! {
     fn get_metering_enabled() -> (bool, bool);
     fn get_data_parameter_index(data_type: DspParameterDataType) -> i32;
     fn get_parameter_count() -> i32;
-    // FIXME add type-agnostic version
+    // the type-agnostic DSP#[] and DSP#[]= are in lib/libfmod.rb
     fn set_parameter_bool(index: i32, value: bool) -> ();
     fn get_parameter_bool(index: i32) -> bool;
     fn get_parameter_data(index: i32) -> magnus::RString;
@@ -73,7 +179,6 @@ extern_struct_fns! {
     fn get_parameter_float(index: i32) -> f32;
     fn set_parameter_int(index: i32, value: i32) -> ();
     fn get_parameter_int(index: i32) -> i32;
-    // TODO parameter info
     fn set_active(active: bool) -> ();
     fn get_active() -> bool;
     fn set_bypass(bypass: bool) -> ();
@@ -98,6 +203,8 @@ extern_struct_bind! {
     fn disconnect_from -> 2;
     fn reset -> 0;
     fn release -> 0;
+    fn set_owned_by_ruby -> 1;
+    fn is_owned_by_ruby -> 0;
     fn get_type -> 0;
     fn get_cpu_usage -> 0;
     fn get_userdata -> 0;
@@ -112,10 +219,12 @@ extern_struct_bind! {
     fn get_parameter_bool -> 1;
     fn set_parameter_data -> 2;
     fn get_parameter_data -> 1;
+    fn get_parameter_data_value -> 1;
     fn set_parameter_float -> 2;
     fn get_parameter_float -> 1;
     fn set_parameter_int -> 2;
     fn get_parameter_int -> 1;
+    fn get_parameter_info -> 1;
     fn set_active -> 1;
     fn get_active -> 0;
     fn set_bypass -> 1;
@@ -124,11 +233,36 @@ extern_struct_bind! {
     fn get_wet_dry_mix -> 0;
     fn get_idle -> 0;
     ruby_compat_methods: true
+
+    |class| {
+      class.const_set("CLASSES", magnus::RHash::new())?;
+      class.define_singleton_method("register_type", magnus::function!(DSP::register_type, 2))?;
+    }
   }
 }
 
 pub fn bind(module: magnus::RModule) -> Result<()> {
+    use fmod::ffi::FMOD_DSP_PARAMETER_TYPE;
+
     fmod::Dsp::bind(module)?;
 
+    let types = module.define_module("DspParameterType")?;
+    types.const_set(
+        "Float",
+        FMOD_DSP_PARAMETER_TYPE::FMOD_DSP_PARAMETER_TYPE_FLOAT as i32,
+    )?;
+    types.const_set(
+        "Int",
+        FMOD_DSP_PARAMETER_TYPE::FMOD_DSP_PARAMETER_TYPE_INT as i32,
+    )?;
+    types.const_set(
+        "Bool",
+        FMOD_DSP_PARAMETER_TYPE::FMOD_DSP_PARAMETER_TYPE_BOOL as i32,
+    )?;
+    types.const_set(
+        "Data",
+        FMOD_DSP_PARAMETER_TYPE::FMOD_DSP_PARAMETER_TYPE_DATA as i32,
+    )?;
+
     Ok(())
 }
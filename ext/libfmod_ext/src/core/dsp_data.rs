@@ -0,0 +1,121 @@
+// Copyright (c) 2024 Lily Lyons
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+use fmod::ffi::{
+    FMOD_3D_ATTRIBUTES, FMOD_DSP_PARAMETER_3DATTRIBUTES, FMOD_DSP_PARAMETER_3DATTRIBUTES_MULTI,
+    FMOD_DSP_PARAMETER_ATTENUATION_RANGE, FMOD_DSP_PARAMETER_FFT, FMOD_DSP_PARAMETER_OVERALLGAIN,
+    FMOD_DSP_PARAMETER_SIDECHAIN, FMOD_VECTOR,
+};
+use magnus::{prelude::*, IntoValue};
+
+use crate::{IntoRuby, Result};
+
+use super::structs::Attributes3D;
+
+fn vector(v: FMOD_VECTOR) -> fmod::Vector {
+    fmod::Vector {
+        x: v.x,
+        y: v.y,
+        z: v.z,
+    }
+}
+
+fn attributes(a: FMOD_3D_ATTRIBUTES) -> Result<Attributes3D> {
+    fmod::Attributes3D {
+        position: vector(a.position),
+        velocity: vector(a.velocity),
+        forward: vector(a.forward),
+        up: vector(a.up),
+    }
+    .into_ruby()
+}
+
+// FMOD hands these out as a pointer and a size, so check the size before reading the struct
+fn read<T: Copy>(bytes: &[u8]) -> Result<T> {
+    if bytes.len() < std::mem::size_of::<T>() {
+        return Err(magnus::Error::new(
+            crate::error::class(),
+            format!(
+                "expected {} bytes of parameter data, got {}",
+                std::mem::size_of::<T>(),
+                bytes.len()
+            ),
+        ));
+    }
+    Ok(unsafe { bytes.as_ptr().cast::<T>().read_unaligned() })
+}
+
+/// Decodes the data of a built in data parameter type into a hash.
+///
+/// Must be called right after getting the data, FMOD only keeps it (and what it points to) valid until the DSP runs again.
+pub fn decode(
+    ruby: &magnus::Ruby,
+    data_type: fmod::DspParameterDataType,
+    bytes: &[u8],
+) -> Result<magnus::Value> {
+    let hash = ruby.hash_new();
+    match data_type {
+        fmod::DspParameterDataType::OverAlign => {
+            let gain: FMOD_DSP_PARAMETER_OVERALLGAIN = read(bytes)?;
+            hash.aset(ruby.to_symbol("linear_gain"), gain.linear_gain)?;
+            hash.aset(
+                ruby.to_symbol("linear_gain_additive"),
+                gain.linear_gain_additive,
+            )?;
+        }
+        fmod::DspParameterDataType::Attributes3D => {
+            let attributes_3d: FMOD_DSP_PARAMETER_3DATTRIBUTES = read(bytes)?;
+            hash.aset(
+                ruby.to_symbol("relative"),
+                attributes(attributes_3d.relative)?,
+            )?;
+            hash.aset(
+                ruby.to_symbol("absolute"),
+                attributes(attributes_3d.absolute)?,
+            )?;
+        }
+        fmod::DspParameterDataType::Attributes3DMulti => {
+            let multi: FMOD_DSP_PARAMETER_3DATTRIBUTES_MULTI = read(bytes)?;
+            let count = (multi.numlisteners.max(0) as usize).min(multi.relative.len());
+            let listeners = ruby.ary_new_capa(count);
+            for (relative, weight) in multi.relative.iter().zip(multi.weight).take(count) {
+                let listener = ruby.hash_new();
+                listener.aset(ruby.to_symbol("relative"), attributes(*relative)?)?;
+                listener.aset(ruby.to_symbol("weight"), weight)?;
+                listeners.push(listener)?;
+            }
+            hash.aset(ruby.to_symbol("listeners"), listeners)?;
+            hash.aset(ruby.to_symbol("absolute"), attributes(multi.absolute)?)?;
+        }
+        fmod::DspParameterDataType::Sidechain => {
+            let sidechain: FMOD_DSP_PARAMETER_SIDECHAIN = read(bytes)?;
+            hash.aset(ruby.to_symbol("enabled"), sidechain.sidechainenable != 0)?;
+        }
+        fmod::DspParameterDataType::FFT => {
+            let fft: FMOD_DSP_PARAMETER_FFT = read(bytes)?;
+            let length = fft.length.max(0) as usize;
+            let count = (fft.numchannels.max(0) as usize).min(fft.spectrum.len());
+            let channels = ruby.ary_new_capa(count);
+            for &spectrum in fft.spectrum.iter().take(count) {
+                let bins: &[f32] = if spectrum.is_null() {
+                    &[]
+                } else {
+                    unsafe { std::slice::from_raw_parts(spectrum, length) }
+                };
+                channels.push(ruby.ary_from_iter(bins.iter().copied()))?;
+            }
+            hash.aset(ruby.to_symbol("length"), length)?;
+            hash.aset(ruby.to_symbol("channels"), channels)?;
+        }
+        fmod::DspParameterDataType::AttenuationRange => {
+            let range: FMOD_DSP_PARAMETER_ATTENUATION_RANGE = read(bytes)?;
+            hash.aset(ruby.to_symbol("min"), range.min)?;
+            hash.aset(ruby.to_symbol("max"), range.max)?;
+        }
+        // user data types (and any FMOD adds later) have no layout we know of
+        _ => return Ok(ruby.str_from_slice(bytes).into_value()),
+    }
+    Ok(hash.as_value())
+}
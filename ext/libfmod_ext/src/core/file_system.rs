@@ -0,0 +1,329 @@
+// Copyright (c) 2024 Lily Lyons
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+use std::{
+    ffi::{c_char, c_uint, c_void, CStr},
+    sync::{Arc, Mutex, Weak},
+};
+
+use fmod::ffi::{FMOD_CREATESOUNDEXINFO, FMOD_FILE_OPEN_CALLBACK, FMOD_RESULT};
+use magnus::{
+    prelude::*,
+    value::{InnerValue, Opaque},
+};
+use once_cell::sync::Lazy;
+
+use crate::{callback, error::check, IntoRuby, Result};
+
+// FMOD tends to read in small pieces, so we read this much from ruby at once to save on trips to the callback thread
+const READ_AHEAD: usize = 64 * 1024;
+// FMOD_MAX_SYSTEMS
+const MAX_SYSTEMS: usize = 8;
+
+/// A ruby object that responds to `read`, `seek` and `size`, that FMOD reads files from.
+pub struct Source {
+    io: Opaque<magnus::Value>,
+    name: String,
+    // whether we opened this (through `System#set_file_system`), and have to close it again
+    close: bool,
+}
+
+impl std::fmt::Debug for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+// every live source, so their io objects can be marked
+static SOURCES: Lazy<Mutex<Vec<Weak<Source>>>> = Lazy::new(Default::default);
+// the object passed to `System#set_file_system` for each system. FMOD doesn't tell the open callback which system
+// it's for, so each slot gets its own open callback
+type Opener = (fmod::System, Opaque<magnus::Value>);
+static OPENERS: Lazy<Mutex<[Option<Opener>; MAX_SYSTEMS]>> = Lazy::new(Default::default);
+
+const OPEN_CALLBACKS: [FMOD_FILE_OPEN_CALLBACK; MAX_SYSTEMS] = [
+    Some(open_system::<0>),
+    Some(open_system::<1>),
+    Some(open_system::<2>),
+    Some(open_system::<3>),
+    Some(open_system::<4>),
+    Some(open_system::<5>),
+    Some(open_system::<6>),
+    Some(open_system::<7>),
+];
+
+#[derive(magnus::TypedData)]
+#[magnus(class = "FMOD::FileSystem", mark)]
+struct _FileSystem;
+
+impl magnus::DataTypeFunctions for _FileSystem {
+    fn mark(&self, marker: &magnus::gc::Marker) {
+        let ruby = magnus::Ruby::get().unwrap();
+        for source in SOURCES.lock().unwrap().iter().filter_map(Weak::upgrade) {
+            marker.mark(source.io.get_inner_with(&ruby));
+        }
+        for (_, opener) in OPENERS.lock().unwrap().iter().flatten() {
+            marker.mark(opener.get_inner_with(&ruby));
+        }
+    }
+}
+
+impl Source {
+    /// Must be called with the GVL held, so `io` can't be collected before it's registered.
+    pub fn new(io: magnus::Value, name: String, close: bool) -> Arc<Self> {
+        let source = Arc::new(Source {
+            io: io.into(),
+            name,
+            close,
+        });
+        let mut sources = SOURCES.lock().unwrap();
+        sources.retain(|source| source.strong_count() > 0);
+        sources.push(Arc::downgrade(&source));
+        source
+    }
+
+    /// Makes a sound created with `ex_info` read from this source.
+    ///
+    /// The source needs to outlive any sounds created with `ex_info`.
+    pub fn apply(self: &Arc<Self>, ex_info: &mut FMOD_CREATESOUNDEXINFO) {
+        ex_info.fileuseropen = Some(open_source);
+        ex_info.fileuserclose = Some(close);
+        ex_info.fileuserread = Some(read);
+        ex_info.fileuserseek = Some(seek);
+        ex_info.fileuserdata = Arc::as_ptr(self).cast_mut().cast();
+    }
+}
+
+struct File {
+    source: Arc<Source>,
+    position: usize,
+    // where the io is, so we don't seek when reading sequentially
+    io_position: Option<usize>,
+    buffer: Vec<u8>,
+    buffer_start: usize,
+}
+
+impl File {
+    fn buffered(&self) -> &[u8] {
+        let end = self.buffer_start + self.buffer.len();
+        if (self.buffer_start..end).contains(&self.position) {
+            &self.buffer[self.position - self.buffer_start..]
+        } else {
+            &[]
+        }
+    }
+
+    // returns false at the end of the file
+    fn fill(&mut self, wanted: usize) -> fmod::Result<bool> {
+        let io = self.source.io;
+        let position = self.position;
+        let seek = self.io_position != Some(position);
+        let length = wanted.max(READ_AHEAD);
+
+        let data = callback::call("file_read", &*self.source, move |ruby| {
+            let io = io.get_inner_with(ruby);
+            if seek {
+                let _: magnus::Value = io.funcall("seek", (position,))?;
+            }
+            let data: Option<magnus::RString> = io.funcall("read", (length,))?;
+            // copy while we still have the GVL, FMOD's thread does the rest
+            let data = data.map(|data| unsafe { data.as_slice() }.to_vec());
+            Ok(Some(data.unwrap_or_default()))
+        })?;
+        // the callback timed out
+        let data = data.ok_or(fmod::Error::Fmod(FMOD_RESULT::FMOD_ERR_FILE_BAD))?;
+
+        self.io_position = Some(position + data.len());
+        self.buffer_start = position;
+        self.buffer = data;
+        Ok(!self.buffer.is_empty())
+    }
+}
+
+fn open_file(source: Arc<Source>, filesize: *mut c_uint, handle: *mut *mut c_void) -> FMOD_RESULT {
+    let io = source.io;
+    let size = callback::call("file_open", &*source, move |ruby| {
+        let size: usize = io.get_inner_with(ruby).funcall("size", ())?;
+        Ok(Some(size))
+    });
+    let Ok(Some(size)) = size else {
+        return FMOD_RESULT::FMOD_ERR_FILE_BAD;
+    };
+
+    let file = Box::new(File {
+        source,
+        position: 0,
+        io_position: None,
+        buffer: Vec::new(),
+        buffer_start: 0,
+    });
+    unsafe {
+        *filesize = size.try_into().unwrap_or(c_uint::MAX);
+        *handle = Box::into_raw(file).cast();
+    }
+    FMOD_RESULT::FMOD_OK
+}
+
+unsafe extern "C" fn open_source(
+    _name: *const c_char,
+    filesize: *mut c_uint,
+    handle: *mut *mut c_void,
+    userdata: *mut c_void,
+) -> FMOD_RESULT {
+    // userdata is the source from `Source::apply`. each file gets its own reference to it
+    let source = userdata.cast::<Source>().cast_const();
+    let source = unsafe {
+        Arc::increment_strong_count(source);
+        Arc::from_raw(source)
+    };
+    open_file(source, filesize, handle)
+}
+
+unsafe extern "C" fn open_system<const SLOT: usize>(
+    name: *const c_char,
+    filesize: *mut c_uint,
+    handle: *mut *mut c_void,
+    _userdata: *mut c_void,
+) -> FMOD_RESULT {
+    let Some((_, opener)) = OPENERS.lock().unwrap()[SLOT] else {
+        return FMOD_RESULT::FMOD_ERR_FILE_NOTFOUND;
+    };
+    let name = unsafe { CStr::from_ptr(name) }
+        .to_string_lossy()
+        .into_owned();
+
+    let source = callback::call("file_open", &name.clone(), move |ruby| {
+        let opener = opener.get_inner_with(ruby);
+        let method = if opener.is_kind_of(ruby.class_proc()) {
+            "call"
+        } else {
+            "open"
+        };
+        let io: Option<magnus::Value> = opener.funcall(method, (name.as_str(),))?;
+        Ok(io.map(|io| Source::new(io, name, true)))
+    });
+    match source {
+        Ok(Some(source)) => open_file(source, filesize, handle),
+        Ok(None) => FMOD_RESULT::FMOD_ERR_FILE_NOTFOUND,
+        Err(_) => FMOD_RESULT::FMOD_ERR_FILE_BAD,
+    }
+}
+
+unsafe extern "C" fn close(handle: *mut c_void, _userdata: *mut c_void) -> FMOD_RESULT {
+    let file = unsafe { Box::from_raw(handle.cast::<File>()) };
+    if file.source.close {
+        let source = file.source;
+        // nothing is waiting on this, so don't hold up FMOD
+        callback::process(move |ruby| {
+            let io = source.io.get_inner_with(ruby);
+            if let Err(e) = io.funcall::<_, _, magnus::Value>("close", ()) {
+                callback::report_error(ruby, e);
+            }
+        });
+    }
+    FMOD_RESULT::FMOD_OK
+}
+
+unsafe extern "C" fn read(
+    handle: *mut c_void,
+    buffer: *mut c_void,
+    sizebytes: c_uint,
+    bytesread: *mut c_uint,
+    _userdata: *mut c_void,
+) -> FMOD_RESULT {
+    let file = unsafe { &mut *handle.cast::<File>() };
+    let out = unsafe { std::slice::from_raw_parts_mut(buffer.cast::<u8>(), sizebytes as usize) };
+
+    let mut written = 0;
+    let mut result = FMOD_RESULT::FMOD_OK;
+    while written < out.len() {
+        let buffered = file.buffered();
+        if buffered.is_empty() {
+            match file.fill(out.len() - written) {
+                Ok(true) => continue,
+                Ok(false) => result = FMOD_RESULT::FMOD_ERR_FILE_EOF,
+                Err(_) => result = FMOD_RESULT::FMOD_ERR_FILE_BAD,
+            }
+            break;
+        }
+        let count = buffered.len().min(out.len() - written);
+        out[written..written + count].copy_from_slice(&buffered[..count]);
+        file.position += count;
+        written += count;
+    }
+
+    unsafe { *bytesread = written as c_uint };
+    result
+}
+
+unsafe extern "C" fn seek(handle: *mut c_void, pos: c_uint, _userdata: *mut c_void) -> FMOD_RESULT {
+    // the io is only seeked when we actually need to read from it
+    let file = unsafe { &mut *handle.cast::<File>() };
+    file.position = pos as usize;
+    FMOD_RESULT::FMOD_OK
+}
+
+/// Opens every file `system` loads through `opener`, or FMOD's own file system if `opener` is `nil`.
+///
+/// `opener` is either a proc, or something that responds to `open` (like `File`), that is called with the file
+/// name and returns something that responds to `read`, `seek`, `size` and `close`, or `nil` if there's no such file.
+pub fn set(system: fmod::System, opener: Option<magnus::Value>) -> Result<()> {
+    let mut openers = OPENERS.lock().unwrap();
+    let slot = openers
+        .iter()
+        .position(|o| matches!(o, Some((s, _)) if *s == system))
+        .or_else(|| openers.iter().position(Option::is_none));
+    let Some(slot) = slot else {
+        drop(openers);
+        return Err(magnus::Error::new(
+            magnus::exception::runtime_error(),
+            "too many systems with a file system",
+        ));
+    };
+
+    let raw = system.into();
+    let Some(opener) = opener.filter(|o| !o.is_nil()) else {
+        openers[slot] = None;
+        let r = unsafe {
+            fmod::ffi::FMOD_System_SetFileSystem(raw, None, None, None, None, None, None, -1)
+        };
+        // raising allocates, which can run the GC, which marks the openers
+        drop(openers);
+        return check(r).into_ruby();
+    };
+
+    openers[slot] = Some((system, opener.into()));
+    let r = unsafe {
+        fmod::ffi::FMOD_System_SetFileSystem(
+            raw,
+            OPEN_CALLBACKS[slot],
+            Some(close),
+            Some(read),
+            Some(seek),
+            None,
+            None,
+            -1,
+        )
+    };
+    drop(openers);
+    check(r).into_ruby()
+}
+
+/// Frees the file system slot used by `system`, once it's been released.
+pub fn forget(system: fmod::System) {
+    let mut openers = OPENERS.lock().unwrap();
+    for opener in openers.iter_mut() {
+        if matches!(opener, Some((s, _)) if *s == system) {
+            *opener = None;
+        }
+    }
+}
+
+pub fn bind(module: magnus::RModule) -> Result<()> {
+    module.define_class("FileSystem", magnus::class::basic_object())?;
+    module.ivar_set("__file_system", _FileSystem)?;
+
+    Ok(())
+}
@@ -25,6 +25,17 @@ impl Geometry {
         geometry.release().into_ruby()
     }
 
+    fn set_owned_by_ruby(rb_self: RbGeometry, owned: bool) -> Result<()> {
+        let geometry: fmod::Geometry = rb_self.from_ruby()?;
+        crate::extern_struct_storage::set_owned(geometry, rb_self, owned);
+        Ok(())
+    }
+
+    fn is_owned_by_ruby(rb_self: RbGeometry) -> Result<bool> {
+        let geometry: fmod::Geometry = rb_self.from_ruby()?;
+        Ok(crate::extern_struct_storage::is_owned(geometry))
+    }
+
     fn get_userdata(rb_self: RbGeometry) -> Result<magnus::Value> {
         rb_self.ivar_get("__userdata")
     }
@@ -91,6 +102,8 @@ extern_struct_bind! {
     fn get_userdata -> 0;
     fn set_userdata -> 1;
     fn release -> 0;
+    fn set_owned_by_ruby -> 1;
+    fn is_owned_by_ruby -> 0;
     fn save -> 0;
     fn set_polygon_attributes -> 4;
     fn get_polygon_attributes -> 1;
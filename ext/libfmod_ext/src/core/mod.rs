@@ -8,13 +8,18 @@ mod channel;
 mod channel_callback;
 mod channel_control;
 pub mod channel_group;
+mod custom_dsp;
 pub mod dsp;
 mod dsp_connection;
+mod dsp_data;
+mod file_system;
 mod geometry;
+mod pcm;
 mod reverb_3d;
 mod rolloff_callback;
 pub mod sound;
 pub mod sound_builder;
+mod sound_future;
 mod sound_group;
 mod sync_point;
 pub mod system;
@@ -42,6 +47,10 @@ pub fn bind(module: magnus::RModule) -> Result<()> {
     dsp_connection::bind(module)?;
     sync_point::bind(module)?;
     sound_builder::bind(module)?;
+    file_system::bind(module)?;
+    pcm::bind(module)?;
+    sound_future::bind(module)?;
+    custom_dsp::bind(module)?;
 
     Ok(())
 }
@@ -0,0 +1,292 @@
+// Copyright (c) 2024 Lily Lyons
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+use std::{
+    cell::UnsafeCell,
+    collections::HashMap,
+    ffi::{c_int, c_uint, c_void},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, Weak,
+    },
+};
+
+use fmod::ffi::{FMOD_CREATESOUNDEXINFO, FMOD_RESULT, FMOD_SOUND, FMOD_TIMEUNIT};
+use magnus::{
+    prelude::*,
+    value::{InnerValue, Opaque},
+};
+use once_cell::sync::Lazy;
+
+use crate::{callback, Result};
+
+/// Where a user created sound gets its PCM data from.
+pub enum Pcm {
+    /// A ruby block, called with the number of bytes FMOD wants and returning a string of (at most) that many.
+    Block(Opaque<magnus::block::Proc>),
+    /// Samples pushed from ruby with `PCMStream#write`, so FMOD never has to wait on ruby.
+    Stream(RingBuffer),
+}
+
+impl std::fmt::Debug for Pcm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pcm::Block(_) => f.write_str("PCM block"),
+            Pcm::Stream(_) => f.write_str("PCM stream"),
+        }
+    }
+}
+
+// every live block, so they can be marked
+static PCMS: Lazy<Mutex<Vec<Weak<Pcm>>>> = Lazy::new(Default::default);
+// the sound each pcm belongs to. the sound's storage entry holds the strong reference
+static SOUNDS: Lazy<Mutex<HashMap<fmod::Sound, Weak<Pcm>>>> = Lazy::new(Default::default);
+
+#[derive(magnus::TypedData)]
+#[magnus(class = "FMOD::PCM", mark)]
+struct _Pcm;
+
+impl magnus::DataTypeFunctions for _Pcm {
+    fn mark(&self, marker: &magnus::gc::Marker) {
+        let ruby = magnus::Ruby::get().unwrap();
+        for pcm in PCMS.lock().unwrap().iter().filter_map(Weak::upgrade) {
+            if let Pcm::Block(block) = &*pcm {
+                marker.mark(block.get_inner_with(&ruby));
+            }
+        }
+    }
+}
+
+/// A single producer, single consumer byte queue that only ever holds whole frames.
+pub struct RingBuffer {
+    buffer: Box<[UnsafeCell<u8>]>,
+    frame_size: usize,
+    // both only ever increase (wrapping), the difference is how much is buffered
+    read: AtomicUsize,
+    write: AtomicUsize,
+    underruns: AtomicU64,
+}
+
+// the producer only touches the free part of the buffer, and the consumer only the filled part
+unsafe impl Sync for RingBuffer {}
+
+impl RingBuffer {
+    fn new(capacity: usize, frame_size: usize) -> Self {
+        let capacity = capacity.max(frame_size) / frame_size * frame_size;
+        Self {
+            buffer: (0..capacity).map(|_| UnsafeCell::new(0)).collect(),
+            frame_size,
+            read: AtomicUsize::new(0),
+            write: AtomicUsize::new(0),
+            underruns: AtomicU64::new(0),
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn len(&self) -> usize {
+        let write = self.write.load(Ordering::Acquire);
+        let read = self.read.load(Ordering::Acquire);
+        write.wrapping_sub(read)
+    }
+
+    fn ptr(&self) -> *mut u8 {
+        UnsafeCell::raw_get(self.buffer.as_ptr())
+    }
+
+    // copies `len` bytes between `data` and the ring starting at `position`, wrapping around
+    unsafe fn copy(&self, position: usize, data: *mut u8, len: usize, into_ring: bool) {
+        let start = position % self.capacity();
+        let first = len.min(self.capacity() - start);
+        let ring = self.ptr();
+        unsafe {
+            if into_ring {
+                std::ptr::copy_nonoverlapping(data, ring.add(start), first);
+                std::ptr::copy_nonoverlapping(data.add(first), ring, len - first);
+            } else {
+                std::ptr::copy_nonoverlapping(ring.add(start), data, first);
+                std::ptr::copy_nonoverlapping(ring, data.add(first), len - first);
+            }
+        }
+    }
+
+    /// Called from ruby. Returns how many bytes were written, which is always a whole number of frames.
+    fn push(&self, data: &[u8]) -> usize {
+        let write = self.write.load(Ordering::Relaxed);
+        let free = self.capacity() - self.len();
+        let len = data.len().min(free) / self.frame_size * self.frame_size;
+        unsafe { self.copy(write, data.as_ptr().cast_mut(), len, true) };
+        self.write.store(write.wrapping_add(len), Ordering::Release);
+        len
+    }
+
+    /// Called from FMOD. Anything that isn't buffered yet is filled with silence.
+    fn pop(&self, out: &mut [u8]) {
+        let read = self.read.load(Ordering::Relaxed);
+        let len = out.len().min(self.len());
+        unsafe { self.copy(read, out.as_mut_ptr(), len, false) };
+        self.read.store(read.wrapping_add(len), Ordering::Release);
+
+        if len < out.len() {
+            out[len..].fill(0);
+            self.underruns.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+fn register(pcm: Pcm) -> Arc<Pcm> {
+    let pcm = Arc::new(pcm);
+    let mut pcms = PCMS.lock().unwrap();
+    pcms.retain(|pcm| pcm.strong_count() > 0);
+    pcms.push(Arc::downgrade(&pcm));
+    pcm
+}
+
+impl Pcm {
+    pub fn block(block: magnus::block::Proc) -> Arc<Self> {
+        register(Pcm::Block(block.into()))
+    }
+
+    pub fn stream(capacity: usize, frame_size: usize) -> Arc<Self> {
+        register(Pcm::Stream(RingBuffer::new(capacity, frame_size)))
+    }
+
+    /// Makes a sound created with `ex_info` read from this.
+    ///
+    /// This needs to outlive the sound, see [`attach`].
+    pub fn apply(self: &Arc<Self>, ex_info: &mut FMOD_CREATESOUNDEXINFO) {
+        ex_info.pcmreadcallback = Some(pcm_read);
+        ex_info.pcmsetposcallback = Some(pcm_set_position);
+        // only used until `attach` is called, after that the sound's userdata belongs to the storage
+        ex_info.userdata = Arc::as_ptr(self).cast_mut().cast();
+    }
+}
+
+/// Ties `pcm` to `sound`, once it's been created. Must be called before the sound is put in the storage.
+pub fn attach(sound: fmod::Sound, pcm: &Arc<Pcm>) {
+    let mut sounds = SOUNDS.lock().unwrap();
+    sounds.retain(|_, pcm| pcm.strong_count() > 0);
+    sounds.insert(sound, Arc::downgrade(pcm));
+}
+
+/// The pcm stream `sound` reads from, if it has one.
+pub fn stream(sound: fmod::Sound) -> Option<Arc<Pcm>> {
+    let sounds = SOUNDS.lock().unwrap();
+    let pcm = sounds.get(&sound).and_then(Weak::upgrade)?;
+    matches!(*pcm, Pcm::Stream(_)).then_some(pcm)
+}
+
+fn find(sound: *mut FMOD_SOUND) -> Option<Arc<Pcm>> {
+    let sounds = SOUNDS.lock().unwrap();
+    if let Some(pcm) = sounds.get(&fmod::Sound::from(sound)) {
+        return pcm.upgrade();
+    }
+    // FMOD calls us while the sound is still being created, before `attach`. the builder is holding on to it then
+    let mut userdata = std::ptr::null_mut();
+    unsafe { fmod::ffi::FMOD_Sound_GetUserData(sound, &mut userdata) };
+    if userdata.is_null() || userdata == crate::extern_struct_storage::DEFAULT_USERDATA_PTR {
+        return None;
+    }
+    let pcm = userdata.cast::<Pcm>().cast_const();
+    unsafe {
+        Arc::increment_strong_count(pcm);
+        Some(Arc::from_raw(pcm))
+    }
+}
+
+unsafe extern "C" fn pcm_read(
+    sound: *mut FMOD_SOUND,
+    data: *mut c_void,
+    datalen: c_uint,
+) -> FMOD_RESULT {
+    let Some(pcm) = find(sound) else {
+        return FMOD_RESULT::FMOD_ERR_INVALID_HANDLE;
+    };
+    let out = unsafe { std::slice::from_raw_parts_mut(data.cast::<u8>(), datalen as usize) };
+
+    match &*pcm {
+        Pcm::Stream(ring) => ring.pop(out),
+        Pcm::Block(block) => {
+            let block = *block;
+            let len = out.len();
+            let result = callback::call("pcm_read", &*pcm, move |ruby| {
+                let data: magnus::RString = block.get_inner_with(ruby).call((len,))?;
+                // copy while we still have the GVL
+                Ok(Some(unsafe { data.as_slice() }.to_vec()))
+            });
+            // anything the block didn't fill (or everything, if it failed) is silence
+            let data = result.ok().flatten().unwrap_or_default();
+            let len = data.len().min(out.len());
+            out[..len].copy_from_slice(&data[..len]);
+            out[len..].fill(0);
+        }
+    }
+    FMOD_RESULT::FMOD_OK
+}
+
+unsafe extern "C" fn pcm_set_position(
+    _sound: *mut FMOD_SOUND,
+    _subsound: c_int,
+    _position: c_uint,
+    _postype: FMOD_TIMEUNIT,
+) -> FMOD_RESULT {
+    // generated audio has no position to seek to, the next read just carries on
+    FMOD_RESULT::FMOD_OK
+}
+
+/// Ruby handle for pushing samples into a user created sound.
+#[magnus::wrap(class = "FMOD::PCMStream", free_immediately, size)]
+pub struct PcmStream(Arc<Pcm>);
+
+impl PcmStream {
+    pub fn new(pcm: Arc<Pcm>) -> Self {
+        Self(pcm)
+    }
+
+    fn ring(&self) -> &RingBuffer {
+        match &*self.0 {
+            Pcm::Stream(ring) => ring,
+            Pcm::Block(_) => unreachable!("PCMStream is only created for streams"),
+        }
+    }
+
+    fn write(&self, data: magnus::RString) -> usize {
+        // this doesn't release the GVL, so the string can't change under us
+        self.ring().push(unsafe { data.as_slice() })
+    }
+
+    fn available(&self) -> usize {
+        self.ring().len()
+    }
+
+    fn free(&self) -> usize {
+        self.ring().capacity() - self.ring().len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.ring().capacity()
+    }
+
+    fn underruns(&self) -> u64 {
+        self.ring().underruns.load(Ordering::Relaxed)
+    }
+}
+
+pub fn bind(module: magnus::RModule) -> Result<()> {
+    module.define_class("PCM", magnus::class::basic_object())?;
+    module.ivar_set("__pcm", _Pcm)?;
+
+    let class = module.define_class("PCMStream", magnus::class::object())?;
+    class.undef_default_alloc_func();
+    class.define_method("write", magnus::method!(PcmStream::write, 1))?;
+    class.define_method("available", magnus::method!(PcmStream::available, 0))?;
+    class.define_method("free", magnus::method!(PcmStream::free, 0))?;
+    class.define_method("capacity", magnus::method!(PcmStream::capacity, 0))?;
+    class.define_method("underruns", magnus::method!(PcmStream::underruns, 0))?;
+
+    Ok(())
+}
@@ -25,6 +25,19 @@ impl Reverb3D {
         reverb.release().into_ruby()
     }
 
+    fn set_owned_by_ruby(rb_self: RbReverb3D, owned: bool) -> Result<()> {
+        use crate::FromRuby;
+        let reverb: fmod::Reverb3D = rb_self.from_ruby()?;
+        crate::extern_struct_storage::set_owned(reverb, rb_self, owned);
+        Ok(())
+    }
+
+    fn is_owned_by_ruby(rb_self: RbReverb3D) -> Result<bool> {
+        use crate::FromRuby;
+        let reverb: fmod::Reverb3D = rb_self.from_ruby()?;
+        Ok(crate::extern_struct_storage::is_owned(reverb))
+    }
+
     fn get_userdata(rb_self: RbReverb3D) -> Result<magnus::Value> {
         rb_self.ivar_get("__userdata")
     }
@@ -56,6 +69,8 @@ extern_struct_bind! {
     fn get_userdata -> 0;
     fn set_userdata -> 1;
     fn release -> 0;
+    fn set_owned_by_ruby -> 1;
+    fn is_owned_by_ruby -> 0;
     ruby_compat_methods: true
   }
 }
@@ -21,17 +21,14 @@ impl fmod::RolloffCallback for RolloffCallback {
         channel_control: fmod::ChannelControl,
         distance: std::ffi::c_float,
     ) -> std::ffi::c_float {
-        let (sender, reciever) = oneshot::channel::<std::ffi::c_float>();
-        callback::process(move |_| {
-            let channel_control = channel_control.into_ruby().unwrap();
-            let system: RbSystem = channel_control.funcall("get_system", ()).unwrap();
-            let callback: magnus::RObject = system.ivar_get("__rolloff_callback").unwrap();
-            let result: f32 = callback
-                .funcall("rolloff", (channel_control, distance))
-                .unwrap();
-            sender.send(result).unwrap();
-        });
-        reciever.recv().unwrap()
+        // there's no way to report an error from here, so a failing callback fully attenuates the channel
+        callback::call("rolloff", &channel_control, move |_| {
+            let channel_control = channel_control.into_ruby()?;
+            let system: RbSystem = channel_control.funcall("get_system", ())?;
+            let callback: magnus::RObject = system.ivar_get("__rolloff_callback")?;
+            callback.funcall("rolloff", (channel_control, distance))
+        })
+        .unwrap_or(0.0)
     }
 }
 
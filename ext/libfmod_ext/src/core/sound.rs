@@ -6,14 +6,14 @@
 #![allow(clippy::upper_case_acronyms)]
 use magnus::prelude::*;
 
-use crate::{thread, Bindable, FromRuby, IntoRuby, Result};
+use crate::{error::check, thread, Bindable, FromRuby, IntoRuby, Result};
 
 use crate::{extern_struct, extern_struct_bind, extern_struct_fns};
 
 use super::enums::{OpenState, TimeUnit};
 use super::flags::Mode;
 use super::sound_group::RbSoundGroup;
-use super::structs::{Tag, Vector};
+use super::structs::{FormatInfo, SoundFormatInfo, Tag, Vector};
 use super::sync_point::RbSyncPoint;
 use super::system::RbSystem;
 
@@ -32,12 +32,15 @@ impl Sound {
             index += 1;
         }
 
+        let result = unsafe { thread::without_gvl_no_ubf(|| sound.release()) };
+        // only drop any memory the sound was reading from once FMOD is done with it
         crate::extern_struct_storage::remove(sound);
-        unsafe { thread::without_gvl_no_ubf(|| sound.release()) }.into_ruby()
+        result.into_ruby()
     }
 
     fn get_sub_sound(rb_self: RbSound, index: i32) -> Result<RbSound> {
-        let sound = rb_self.from_ruby()?;
+        let sound: fmod::Sound = rb_self.from_ruby()?;
+        let _scope = crate::extern_struct_storage::OwnerScope::enter(sound);
         unsafe { thread::without_gvl_no_ubf(|| sound.get_sub_sound(index)) }.into_ruby()
     }
 
@@ -49,6 +52,159 @@ impl Sound {
         sound.delete_sync_point(point).into_ruby()
     }
 
+    fn set_owned_by_ruby(rb_self: RbSound, owned: bool) -> Result<()> {
+        let sound: fmod::Sound = rb_self.from_ruby()?;
+        crate::extern_struct_storage::set_owned(sound, rb_self, owned);
+        Ok(())
+    }
+
+    fn is_owned_by_ruby(rb_self: RbSound) -> Result<bool> {
+        let sound: fmod::Sound = rb_self.from_ruby()?;
+        Ok(crate::extern_struct_storage::is_owned(sound))
+    }
+
+    // lock(offset, length) { |part1, part2| ... }
+    //
+    // the parts are copies, anything the block writes to them is copied back before the sound is unlocked
+    fn lock(rb_self: RbSound, offset: u32, length: u32) -> Result<magnus::Value> {
+        let ruby = magnus::Ruby::get().unwrap();
+        let sound: fmod::Sound = rb_self.from_ruby()?;
+        let raw: *mut fmod::ffi::FMOD_SOUND = sound.into();
+        // the block could release the sound (or its system), which frees the locked memory
+        let stamp = crate::extern_struct_storage::stamp(sound);
+
+        let mut ptrs = [std::ptr::null_mut(); 2];
+        let mut lens = [0; 2];
+        let result = unsafe {
+            fmod::ffi::FMOD_Sound_Lock(
+                raw,
+                offset,
+                length,
+                &mut ptrs[0],
+                &mut ptrs[1],
+                &mut lens[0],
+                &mut lens[1],
+            )
+        };
+        check(result).into_ruby()?;
+
+        let regions: Vec<&mut [u8]> = ptrs
+            .iter()
+            .zip(lens)
+            .map(|(&ptr, len)| {
+                if ptr.is_null() {
+                    &mut [][..]
+                } else {
+                    unsafe { std::slice::from_raw_parts_mut(ptr.cast::<u8>(), len as usize) }
+                }
+            })
+            .collect();
+        // kept on the stack (not in a Vec) so the GC can see them
+        let parts = [
+            ruby.str_from_slice(&regions[0]),
+            ruby.str_from_slice(&regions[1]),
+        ];
+
+        let value = match ruby.block_proc() {
+            Ok(block) => block.call::<_, magnus::Value>((parts[0], parts[1])),
+            Err(_) => Ok(ruby.ary_new_from_values(&parts).as_value()),
+        };
+
+        if crate::extern_struct_storage::stamp(sound) != stamp {
+            value?;
+            return Err(crate::error::use_after_free(sound));
+        }
+
+        // always write back and unlock, even if the block raised
+        let mut resized = false;
+        for (region, part) in regions.into_iter().zip(&parts) {
+            let data = unsafe { part.as_slice() };
+            if data.len() == region.len() {
+                region.copy_from_slice(data);
+            } else {
+                resized = true;
+            }
+        }
+        let result =
+            unsafe { fmod::ffi::FMOD_Sound_Unlock(raw, ptrs[0], ptrs[1], lens[0], lens[1]) };
+
+        let value = value?;
+        check(result).into_ruby()?;
+        if resized {
+            return Err(magnus::Error::new(
+                magnus::exception::arg_error(),
+                "locked parts can't change size, changes to them were discarded",
+            ));
+        }
+        Ok(value)
+    }
+
+    // read_data(bytes) -> String or nil at the end of the sound, like IO#read
+    fn read_data(rb_self: RbSound, length: usize) -> Result<Option<magnus::RString>> {
+        let ruby = magnus::Ruby::get().unwrap();
+        let sound: fmod::Sound = rb_self.from_ruby()?;
+
+        // FMOD can't read more than the whole sound, so there's no point allocating more than that
+        let total = pcm_bytes(sound).into_ruby()?;
+        let mut buffer = vec![0; length.min(total as usize)];
+        let read =
+            unsafe { thread::without_gvl_no_ubf(|| read_data(sound, &mut buffer)) }.into_ruby()?;
+        if read == 0 && length > 0 {
+            return Ok(None);
+        }
+        Ok(Some(ruby.str_from_slice(&buffer[..read])))
+    }
+
+    fn seek_data(rb_self: RbSound, pcm: u32) -> Result<()> {
+        let sound: fmod::Sound = rb_self.from_ruby()?;
+        unsafe { thread::without_gvl_no_ubf(|| seek_data(sound, pcm)) }.into_ruby()
+    }
+
+    // decodes the whole sound into a wav file at path, returning how many bytes of sample data were written
+    fn decode_to_wav(rb_self: RbSound, path: std::path::PathBuf) -> Result<u64> {
+        let sound: fmod::Sound = rb_self.from_ruby()?;
+        let result = unsafe { thread::without_gvl_no_ubf(|| decode_to_wav(sound, &path)) };
+        match result {
+            Ok(written) => Ok(written),
+            Err(DecodeError::Fmod(e)) => Err(e).into_ruby(),
+            Err(DecodeError::Io(e)) => Err(magnus::Error::new(
+                magnus::exception::io_error(),
+                format!("{}: {e}", path.display()),
+            )),
+        }
+    }
+
+    fn pcm_stream(rb_self: RbSound) -> Result<Option<super::pcm::PcmStream>> {
+        let sound: fmod::Sound = rb_self.from_ruby()?;
+        Ok(super::pcm::stream(sound).map(super::pcm::PcmStream::new))
+    }
+
+    fn get_format(rb_self: RbSound) -> Result<SoundFormatInfo> {
+        let sound: fmod::Sound = rb_self.from_ruby()?;
+        sound
+            .get_format()
+            .map(|(sound_type, format, channels, bits)| FormatInfo {
+                sound_type,
+                format,
+                channels,
+                bits,
+            })
+            .into_ruby()
+    }
+
+    // FMOD copies the list, so it doesn't need to outlive the call
+    fn set_subsound_sentence(rb_self: RbSound, list: Vec<i32>) -> Result<()> {
+        let sound: fmod::Sound = rb_self.from_ruby()?;
+        let result = unsafe {
+            fmod::ffi::FMOD_Sound_SetSubSoundSentence(
+                sound.into(),
+                list.as_ptr().cast_mut(),
+                list.len() as i32,
+            )
+        };
+        check(result).into_ruby()
+    }
+
     fn get_userdata(rb_self: RbSound) -> Result<magnus::Value> {
         rb_self.ivar_get("__userdata")
     }
@@ -109,11 +265,19 @@ extern_struct_bind! {
     fn get_loop_count -> 0;
     fn set_loop_points -> 4;
     fn get_loop_points -> 2;
+    fn lock -> 2;
+    fn pcm_stream -> 0;
+    fn read_data -> 1;
+    fn seek_data -> 1;
+    fn decode_to_wav -> 1;
     fn get_userdata -> 0;
     fn set_userdata -> 1;
     fn release -> 0;
+    fn set_owned_by_ruby -> 1;
+    fn is_owned_by_ruby -> 0;
     fn get_system -> 0;
     fn get_name -> 0;
+    fn get_format -> 0;
     fn get_length -> 1;
     fn get_tag_count -> 0;
     fn get_tag -> 2;
@@ -127,6 +291,7 @@ extern_struct_bind! {
     fn get_sub_sound_count -> 0;
     fn get_sub_sound -> 1;
     fn get_sub_sound_parent -> 0;
+    fn set_subsound_sentence -> 1;
     fn get_sync_point -> 1;
     fn get_sync_point_info -> 2;
     fn add_sync_point -> 3;
@@ -135,8 +300,220 @@ extern_struct_bind! {
   }
 }
 
+// returns how much was read, which is only less than the buffer at the end of the sound
+fn read_data(sound: fmod::Sound, buffer: &mut [u8]) -> fmod::Result<usize> {
+    let mut read = 0;
+    let result = unsafe {
+        fmod::ffi::FMOD_Sound_ReadData(
+            sound.into(),
+            buffer.as_mut_ptr().cast(),
+            buffer.len().try_into().unwrap_or(u32::MAX),
+            &mut read,
+        )
+    };
+    match result {
+        fmod::ffi::FMOD_RESULT::FMOD_ERR_FILE_EOF => Ok(read as usize),
+        result => check(result).map(|()| read as usize),
+    }
+}
+
+fn pcm_bytes(sound: fmod::Sound) -> fmod::Result<u32> {
+    let mut length = 0;
+    check(unsafe {
+        fmod::ffi::FMOD_Sound_GetLength(sound.into(), &mut length, fmod::ffi::FMOD_TIMEUNIT_PCMBYTES)
+    })?;
+    Ok(length)
+}
+
+fn seek_data(sound: fmod::Sound, pcm: u32) -> fmod::Result<()> {
+    check(unsafe { fmod::ffi::FMOD_Sound_SeekData(sound.into(), pcm) })
+}
+
+enum DecodeError {
+    Fmod(fmod::Error),
+    Io(std::io::Error),
+}
+
+impl From<fmod::Error> for DecodeError {
+    fn from(value: fmod::Error) -> Self {
+        Self::Fmod(value)
+    }
+}
+
+impl From<std::io::Error> for DecodeError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+fn decode_to_wav(
+    sound: fmod::Sound,
+    path: &std::path::Path,
+) -> std::result::Result<u64, DecodeError> {
+    use fmod::ffi::FMOD_SOUND_FORMAT;
+    use std::io::{Seek, SeekFrom, Write};
+
+    let raw: *mut fmod::ffi::FMOD_SOUND = sound.into();
+    let (mut kind, mut format, mut channels, mut bits) = (
+        fmod::ffi::FMOD_SOUND_TYPE::FMOD_SOUND_TYPE_UNKNOWN,
+        FMOD_SOUND_FORMAT::FMOD_SOUND_FORMAT_NONE,
+        0,
+        0,
+    );
+    check(unsafe {
+        fmod::ffi::FMOD_Sound_GetFormat(raw, &mut kind, &mut format, &mut channels, &mut bits)
+    })?;
+    let (mut frequency, mut priority) = (0.0, 0);
+    check(unsafe { fmod::ffi::FMOD_Sound_GetDefaults(raw, &mut frequency, &mut priority) })?;
+
+    // WAVE_FORMAT_PCM or WAVE_FORMAT_IEEE_FLOAT, anything else (like FMOD_CREATECOMPRESSEDSAMPLE) can't be decoded
+    let tag: u16 = match format {
+        FMOD_SOUND_FORMAT::FMOD_SOUND_FORMAT_PCM8
+        | FMOD_SOUND_FORMAT::FMOD_SOUND_FORMAT_PCM16
+        | FMOD_SOUND_FORMAT::FMOD_SOUND_FORMAT_PCM24
+        | FMOD_SOUND_FORMAT::FMOD_SOUND_FORMAT_PCM32 => 1,
+        FMOD_SOUND_FORMAT::FMOD_SOUND_FORMAT_PCMFLOAT => 3,
+        _ => return Err(fmod::Error::Fmod(fmod::ffi::FMOD_RESULT::FMOD_ERR_FORMAT).into()),
+    };
+    // FMOD's 8 bit samples are signed, but 8 bit WAV data is unsigned
+    let signed_8_bit = matches!(format, FMOD_SOUND_FORMAT::FMOD_SOUND_FORMAT_PCM8);
+    let wav = WavFormat {
+        tag,
+        channels: channels as u16,
+        rate: frequency as u32,
+        bits: bits as u16,
+    };
+
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    // the sizes are filled in once we know them
+    file.write_all(&wav_header(&wav, 0, 0))?;
+
+    seek_data(sound, 0)?;
+    let mut buffer = vec![0; 64 * 1024];
+    let mut written = 0u64;
+    loop {
+        let read = read_data(sound, &mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        written += read as u64;
+        if wav_sizes(written).is_none() {
+            drop(file);
+            let _ = std::fs::remove_file(path);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "decoded data is too large for a WAV file (over 4 GiB)",
+            )
+            .into());
+        }
+        if signed_8_bit {
+            pcm8_to_unsigned(&mut buffer[..read]);
+        }
+        file.write_all(&buffer[..read])?;
+    }
+    // chunks are padded to an even length
+    if written % 2 == 1 {
+        file.write_all(&[0])?;
+    }
+
+    // checked in the loop above
+    let (riff_size, data_size) = wav_sizes(written).unwrap();
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&wav_header(&wav, riff_size, data_size))?;
+    file.flush()?;
+
+    Ok(written)
+}
+
+struct WavFormat {
+    // WAVE_FORMAT_PCM or WAVE_FORMAT_IEEE_FLOAT
+    tag: u16,
+    channels: u16,
+    rate: u32,
+    bits: u16,
+}
+
+// everything up to the start of the samples
+fn wav_header(format: &WavFormat, riff_size: u32, data_size: u32) -> [u8; 44] {
+    let block_align = format.channels * format.bits / 8;
+    let mut header = [0; 44];
+    header[0..4].copy_from_slice(b"RIFF");
+    header[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    header[8..16].copy_from_slice(b"WAVEfmt ");
+    header[16..20].copy_from_slice(&16u32.to_le_bytes());
+    header[20..22].copy_from_slice(&format.tag.to_le_bytes());
+    header[22..24].copy_from_slice(&format.channels.to_le_bytes());
+    header[24..28].copy_from_slice(&format.rate.to_le_bytes());
+    header[28..32].copy_from_slice(&(format.rate * u32::from(block_align)).to_le_bytes());
+    header[32..34].copy_from_slice(&block_align.to_le_bytes());
+    header[34..36].copy_from_slice(&format.bits.to_le_bytes());
+    header[36..40].copy_from_slice(b"data");
+    header[40..44].copy_from_slice(&data_size.to_le_bytes());
+    header
+}
+
+// the RIFF and data chunk sizes for `written` bytes of samples, or None if they don't fit in a WAV file
+fn wav_sizes(written: u64) -> Option<(u32, u32)> {
+    let data_size = u32::try_from(written).ok()?;
+    let riff_size = data_size.checked_add(36 + (data_size % 2))?;
+    Some((riff_size, data_size))
+}
+
+fn pcm8_to_unsigned(samples: &mut [u8]) {
+    for sample in samples {
+        *sample ^= 0x80;
+    }
+}
+
 pub fn bind(module: magnus::RModule) -> Result<()> {
     fmod::Sound::bind(module)?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wav_header_layout() {
+        let format = WavFormat {
+            tag: 1,
+            channels: 2,
+            rate: 44100,
+            bits: 16,
+        };
+        let header = wav_header(&format, 40, 4);
+        assert_eq!(&header[0..4], b"RIFF");
+        assert_eq!(header[4..8], 40u32.to_le_bytes());
+        assert_eq!(&header[8..16], b"WAVEfmt ");
+        assert_eq!(header[20..22], 1u16.to_le_bytes());
+        assert_eq!(header[22..24], 2u16.to_le_bytes());
+        assert_eq!(header[24..28], 44100u32.to_le_bytes());
+        // byte rate and block align
+        assert_eq!(header[28..32], (44100u32 * 4).to_le_bytes());
+        assert_eq!(header[32..34], 4u16.to_le_bytes());
+        assert_eq!(header[34..36], 16u16.to_le_bytes());
+        assert_eq!(&header[36..40], b"data");
+        assert_eq!(header[40..44], 4u32.to_le_bytes());
+    }
+
+    #[test]
+    fn wav_sizes_pad_and_limit() {
+        assert_eq!(wav_sizes(4), Some((40, 4)));
+        assert_eq!(wav_sizes(5), Some((42, 5)));
+        assert_eq!(wav_sizes(u64::from(u32::MAX) - 36), None);
+        assert_eq!(wav_sizes(u64::from(u32::MAX) + 1), None);
+        assert_eq!(
+            wav_sizes(u64::from(u32::MAX) - 37),
+            Some((u32::MAX - 1, u32::MAX - 37))
+        );
+    }
+
+    #[test]
+    fn pcm8_is_made_unsigned() {
+        let mut samples = [0x80, 0xFF, 0x00, 0x7F];
+        pcm8_to_unsigned(&mut samples);
+        assert_eq!(samples, [0x00, 0x7F, 0x80, 0xFF]);
+    }
+}
@@ -5,7 +5,7 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 #![allow(clippy::upper_case_acronyms)]
 
-use std::cell::RefCell;
+use std::{cell::RefCell, sync::Arc};
 
 use magnus::value::ReprValue;
 use magnus::Object;
@@ -15,11 +15,32 @@ use crate::{Bindable, FromRuby, IntoRuby, Result};
 use crate::extern_struct_bind;
 
 use super::enums::{ChannelOrder, SoundFormat, TimeUnit};
+use super::file_system::Source;
 use super::flags::Mode;
+use super::pcm::Pcm;
 use super::sound_group::RbSoundGroup;
+use super::structs::Guid;
 
 #[magnus::wrap(class = "FMOD::SoundBuilder", free_immediately, size)]
-pub struct SoundBuilder(pub(super) RefCell<Option<fmod::SoundBuilder<'static>>>);
+pub struct SoundBuilder(
+    pub(super) RefCell<Option<fmod::SoundBuilder<'static>>>,
+    // the memory opened with `open_memory` or `open_memory_point`.
+    // sounds created from this builder keep it alive too, so FMOD can keep reading from it after the builder is gone
+    pub(super) Option<Arc<[u8]>>,
+    // the io opened with `open_io`. files FMOD opens through it hold their own reference
+    pub(super) Option<Arc<Source>>,
+    // the PCM callback set up by `open_user`. sounds created from this builder keep it alive too
+    pub(super) Option<Arc<Pcm>>,
+    // memory the builder's ex info points to, that FMOD only reads while creating the sound
+    RefCell<ExInfoMemory>,
+);
+
+#[derive(Default)]
+struct ExInfoMemory {
+    inclusion_list: Option<Box<[i32]>>,
+    fsb_guid: Option<Box<fmod::ffi::FMOD_GUID>>,
+}
+
 type _SoundBuilder = magnus::typed_data::Obj<SoundBuilder>;
 
 unsafe impl Send for SoundBuilder {}
@@ -27,7 +48,13 @@ unsafe impl Sync for SoundBuilder {}
 
 impl IntoRuby<SoundBuilder> for fmod::SoundBuilder<'static> {
     fn into_ruby(self) -> Result<SoundBuilder> {
-        Ok(SoundBuilder(RefCell::new(Some(self))))
+        Ok(SoundBuilder(
+            RefCell::new(Some(self)),
+            None,
+            None,
+            None,
+            RefCell::default(),
+        ))
     }
 }
 
@@ -51,18 +78,121 @@ impl SoundBuilder {
     }
 
     pub fn open_memory(rb_data: magnus::RString) -> Result<_SoundBuilder> {
-        // This is technically REALLY dangerous! We're creating a SoundBuilder with an arbitrary lifetime AND storing a pointer to the inside of the Ruby string.
-        // We can make this better somewhat be freezing the string and storing it but this is still nasty.
-        // What's even worse is that we can't even free the string until the sound is loaded...
-        let data = rb_data.from_ruby()?;
-        let builder = unsafe { fmod::SoundBuilder::open_memory(data) };
-        let rb_self = _SoundBuilder::wrap(builder.into_ruby()?);
-        rb_self.ivar_set("data", rb_data)?;
-        rb_data.freeze();
-        Ok(rb_self)
+        Self::from_memory(rb_data, |data| unsafe {
+            fmod::SoundBuilder::open_memory(data)
+        })
     }
 
-    // no open memory point because that is WAY too dangerous
+    // FMOD reads from the data for as long as the sound exists, which is fine because we own it
+    pub fn open_memory_point(rb_data: magnus::RString) -> Result<_SoundBuilder> {
+        Self::from_memory(rb_data, |data| unsafe {
+            fmod::SoundBuilder::open_memory_point(data)
+        })
+    }
+
+    fn from_memory(
+        rb_data: magnus::RString,
+        open: impl FnOnce(&'static [u8]) -> fmod::SoundBuilder<'static>,
+    ) -> Result<_SoundBuilder> {
+        // copy the string, so ruby can't modify, move or free it out from under FMOD
+        let data: &[u8] = rb_data.from_ruby()?;
+        let data: Arc<[u8]> = data.into();
+        // SAFETY: the data is a heap allocation that never moves, and that outlives the builder and every sound
+        // created from it (see `System#create_sound`)
+        let slice = unsafe { std::slice::from_raw_parts(data.as_ptr(), data.len()) };
+        let builder = open(slice);
+        Ok(_SoundBuilder::wrap(SoundBuilder(
+            RefCell::new(Some(builder)),
+            Some(data),
+            None,
+            None,
+            RefCell::default(),
+        )))
+    }
+
+    pub fn open_io(io: magnus::Value) -> Result<_SoundBuilder> {
+        for method in ["read", "seek", "size"] {
+            if !io.respond_to(method, false)? {
+                return Err(magnus::Error::new(
+                    magnus::exception::arg_error(),
+                    format!("{} does not respond to {method}", io.inspect()),
+                ));
+            }
+        }
+        let source = Source::new(io, io.inspect(), false);
+
+        // the name is never used (the open callback ignores it) but FMOD still needs one
+        let name = unsafe { fmod::Utf8CStr::from_ptr_unchecked(c"io".as_ptr()) };
+        let builder = fmod::SoundBuilder::open(name);
+        let mut ex_info = builder.raw_ex_info();
+        source.apply(&mut ex_info);
+        let builder = unsafe { builder.with_raw_ex_info(ex_info) };
+
+        Ok(_SoundBuilder::wrap(SoundBuilder(
+            RefCell::new(Some(builder)),
+            None,
+            Some(source),
+            None,
+            RefCell::default(),
+        )))
+    }
+
+    // open_user(channels:, frequency:, format:, length:, buffer_size: nil) { |bytes| ... }
+    pub fn open_user(args: &[magnus::Value]) -> Result<_SoundBuilder> {
+        let args = magnus::scan_args::scan_args::<
+            (),
+            (),
+            (),
+            (),
+            magnus::RHash,
+            Option<magnus::block::Proc>,
+        >(args)?;
+        let kwargs =
+            magnus::scan_args::get_kwargs::<_, (i32, i32, SoundFormat, u32), (Option<usize>,), ()>(
+                args.keywords,
+                &["channels", "frequency", "format", "length"],
+                &["buffer_size"],
+            )?;
+        let (channels, frequency, format, length) = kwargs.required;
+        let (buffer_size,) = kwargs.optional;
+        let format: fmod::SoundFormat = format.from_ruby()?;
+
+        let sample_size = match format {
+            fmod::SoundFormat::PCM8 => 1,
+            fmod::SoundFormat::PCM16 => 2,
+            fmod::SoundFormat::PCM24 => 3,
+            fmod::SoundFormat::PCM32 | fmod::SoundFormat::PCMFloat => 4,
+            _ => {
+                return Err(magnus::Error::new(
+                    magnus::exception::arg_error(),
+                    "format must be a PCM format",
+                ))
+            }
+        };
+        let frame_size = sample_size * channels.max(1) as usize;
+
+        let pcm = match args.block {
+            Some(block) => Pcm::block(block),
+            // a second of audio by default
+            None => Pcm::stream(
+                buffer_size.unwrap_or(frame_size * frequency.max(1) as usize),
+                frame_size,
+            ),
+        };
+
+        let builder = fmod::SoundBuilder::open_user(length, channels, frequency, format);
+        let mut ex_info = builder.raw_ex_info();
+        pcm.apply(&mut ex_info);
+        let builder = unsafe { builder.with_raw_ex_info(ex_info) };
+
+        Ok(_SoundBuilder::wrap(SoundBuilder(
+            RefCell::new(Some(builder)),
+            None,
+            None,
+            Some(pcm),
+            RefCell::default(),
+        )))
+    }
 }
 
 impl SoundBuilder {
@@ -115,7 +245,20 @@ impl SoundBuilder {
         Ok(this)
     }
 
-    // no idea how to do inclusion list (possibly use a rstring) but that would be dangerous and result in possibly misaligned pointers
+    // the list is copied, so changing the array afterwards does nothing
+    fn with_inclusion_list(this: _SoundBuilder, list: Vec<i32>) -> Result<_SoundBuilder> {
+        let list: Box<[i32]> = list.into();
+        let mut memory = this.4.borrow_mut();
+        let mut borrow = this.0.borrow_mut();
+        let builder = borrow.take().ok_or_else(Self::invalid_state_error)?;
+        let mut ex_info = builder.raw_ex_info();
+        ex_info.inclusionlist = list.as_ptr().cast_mut();
+        ex_info.inclusionlistnum = list.len() as i32;
+        *borrow = Some(unsafe { builder.with_raw_ex_info(ex_info) });
+        // the old list (if any) isn't pointed to anymore
+        memory.inclusion_list = Some(list);
+        Ok(this)
+    }
 
     fn with_dls_name(this: _SoundBuilder, rb_name: magnus::RString) -> Result<_SoundBuilder> {
         let name = rb_name.from_ruby()?; // do this before so on error the builder is still valid
@@ -235,7 +378,23 @@ impl SoundBuilder {
         Ok(this)
     }
 
-    // no idea how to do fsb guid
+    fn with_fsb_guid(this: _SoundBuilder, guid: Guid) -> Result<_SoundBuilder> {
+        let guid: fmod::Guid = guid.from_ruby()?; // do this before so on error the builder is still valid
+        let guid = Box::new(fmod::ffi::FMOD_GUID {
+            Data1: guid.data_1,
+            Data2: guid.data_2,
+            Data3: guid.data_3,
+            Data4: guid.data_4,
+        });
+        let mut memory = this.4.borrow_mut();
+        let mut borrow = this.0.borrow_mut();
+        let builder = borrow.take().ok_or_else(Self::invalid_state_error)?;
+        let mut ex_info = builder.raw_ex_info();
+        ex_info.fsbguid = std::ptr::from_ref(&*guid).cast_mut();
+        *borrow = Some(unsafe { builder.with_raw_ex_info(ex_info) });
+        memory.fsb_guid = Some(guid);
+        Ok(this)
+    }
 
     pub(super) fn invalid_state_error() -> magnus::Error {
         magnus::Error::new(
@@ -256,8 +415,9 @@ impl SoundBuilder {
         this.ivar_get("name")
     }
 
-    pub fn data(this: _SoundBuilder) -> Result<Option<magnus::RString>> {
-        this.ivar_get("data")
+    pub fn data(&self) -> Option<magnus::RString> {
+        let ruby = magnus::Ruby::get().unwrap();
+        self.1.as_deref().map(|data| ruby.str_from_slice(data))
     }
 
     pub fn length(&self) -> Result<u32> {
@@ -359,6 +519,28 @@ impl SoundBuilder {
         Ok(builder.min_midi_granularity())
     }
 
+    pub fn inclusion_list(&self) -> Option<Vec<i32>> {
+        self.4
+            .borrow()
+            .inclusion_list
+            .as_deref()
+            .map(<[i32]>::to_vec)
+    }
+
+    pub fn fsb_guid(&self) -> Result<Option<Guid>> {
+        let memory = self.4.borrow();
+        let Some(guid) = memory.fsb_guid.as_deref() else {
+            return Ok(None);
+        };
+        let guid = fmod::Guid {
+            data_1: guid.Data1,
+            data_2: guid.Data2,
+            data_3: guid.Data3,
+            data_4: guid.Data4,
+        };
+        guid.into_ruby().map(Some)
+    }
+
     pub fn non_block_thread_id(&self) -> Result<i32> {
         let borrow = self.0.borrow();
         let builder = borrow.as_ref().ok_or_else(Self::invalid_state_error)?;
@@ -373,6 +555,7 @@ extern_struct_bind! {
     fn with_mode -> 1;
     fn with_decode_buffer_size -> 1;
     fn with_initial_subsound -> 1;
+    fn with_inclusion_list -> 1;
     fn with_dls_name -> 1;
     fn with_encryption_key -> 1;
     fn with_max_polyphony -> 1;
@@ -384,6 +567,7 @@ extern_struct_bind! {
     fn with_ignore_set_filesystem -> 1;
     fn with_min_midi_granularity -> 1;
     fn with_non_block_thread_id -> 1;
+    fn with_fsb_guid -> 1;
 
     fn mode -> 0;
     fn name_or_url -> 0;
@@ -395,6 +579,7 @@ extern_struct_bind! {
     fn decode_buffer_size -> 0;
     fn initial_subsound -> 0;
     fn subsound_count -> 0;
+    fn inclusion_list -> 0;
     fn dls_name -> 0;
     fn encryption_key -> 0;
     fn max_polyphony -> 0;
@@ -406,10 +591,14 @@ extern_struct_bind! {
     fn ignore_set_filesystem -> 0;
     fn min_midi_granularity -> 0;
     fn non_block_thread_id -> 0;
+    fn fsb_guid -> 0;
 
     |class| {
       class.define_singleton_method("open", magnus::function!(SoundBuilder::open, 1))?;
       class.define_singleton_method("open_memory", magnus::function!(SoundBuilder::open_memory, 1))?;
+      class.define_singleton_method("open_memory_point", magnus::function!(SoundBuilder::open_memory_point, 1))?;
+      class.define_singleton_method("open_io", magnus::function!(SoundBuilder::open_io, 1))?;
+      class.define_singleton_method("open_user", magnus::function!(SoundBuilder::open_user, -1))?;
     }
   }
 }
@@ -0,0 +1,293 @@
+// Copyright (c) 2024 Lily Lyons
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, Weak,
+    },
+    time::{Duration, Instant},
+};
+
+use fmod::ffi::{FMOD_RESULT, FMOD_SOUND};
+use magnus::{
+    prelude::*,
+    value::{InnerValue, Opaque},
+};
+use once_cell::sync::Lazy;
+
+use crate::{callback, thread, IntoRuby, Result};
+
+use super::sound::RbSound;
+
+/// A sound being loaded in the background with `FMOD_NONBLOCKING`.
+pub struct Loading {
+    system: fmod::System,
+    sound: fmod::Sound,
+    state: Mutex<State>,
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    // None until FMOD calls the nonblock callback
+    result: Option<FMOD_RESULT>,
+    on_ready: Vec<Opaque<magnus::block::Proc>>,
+}
+
+impl std::fmt::Debug for Loading {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Loading").field(&self.sound).finish()
+    }
+}
+
+enum Slot {
+    Waiting(Weak<Loading>),
+    // FMOD finished before `create_sound` returned the sound to us
+    Early(FMOD_RESULT),
+}
+
+// every sound loaded with `create_sound_async`. the sound's storage entry holds the strong reference.
+// entries stay after loading so later nonblock callbacks (from seeking a stream, for example) are ignored
+static LOADING: Lazy<Mutex<HashMap<fmod::Sound, Slot>>> = Lazy::new(Default::default);
+
+#[derive(magnus::TypedData)]
+#[magnus(class = "FMOD::SoundLoading", mark)]
+struct _SoundLoading;
+
+impl magnus::DataTypeFunctions for _SoundLoading {
+    fn mark(&self, marker: &magnus::gc::Marker) {
+        let ruby = magnus::Ruby::get().unwrap();
+        for slot in LOADING.lock().unwrap().values() {
+            let Slot::Waiting(loading) = slot else {
+                continue;
+            };
+            let Some(loading) = loading.upgrade() else {
+                continue;
+            };
+            for block in &loading.state.lock().unwrap().on_ready {
+                marker.mark(block.get_inner_with(&ruby));
+            }
+        }
+    }
+}
+
+/// Makes `builder` load in the background and report back to [`register`].
+pub fn prepare(builder: fmod::SoundBuilder<'static>) -> fmod::SoundBuilder<'static> {
+    let mode = builder.mode() | fmod::Mode::NONBLOCKING;
+    let builder = builder.with_mode(mode);
+    let mut ex_info = builder.raw_ex_info();
+    ex_info.nonblockcallback = Some(nonblock_callback);
+    unsafe { builder.with_raw_ex_info(ex_info) }
+}
+
+/// Starts tracking `sound`, which was created from a builder passed through [`prepare`].
+///
+/// The returned value needs to live as long as the sound does.
+pub fn register(system: fmod::System, sound: fmod::Sound) -> Arc<Loading> {
+    let mut loading = LOADING.lock().unwrap();
+    loading.retain(|_, slot| !matches!(slot, Slot::Waiting(l) if l.strong_count() == 0));
+
+    let result = match loading.get(&sound) {
+        Some(Slot::Early(result)) => Some(*result),
+        _ => None,
+    };
+    let state = Arc::new(Loading {
+        system,
+        sound,
+        state: Mutex::new(State {
+            result,
+            on_ready: Vec::new(),
+        }),
+        ready: Condvar::new(),
+    });
+    loading.insert(sound, Slot::Waiting(Arc::downgrade(&state)));
+    state
+}
+
+unsafe extern "C" fn nonblock_callback(sound: *mut FMOD_SOUND, result: FMOD_RESULT) -> FMOD_RESULT {
+    let sound = fmod::Sound::from(sound);
+    let loading = {
+        let mut loading = LOADING.lock().unwrap();
+        let waiting = match loading.get(&sound) {
+            Some(Slot::Waiting(loading)) => loading.upgrade(),
+            Some(Slot::Early(_)) => return FMOD_RESULT::FMOD_OK,
+            None => None,
+        };
+        // a dead entry is from an earlier sound FMOD has since reused the handle of
+        if waiting.is_none() {
+            loading.insert(sound, Slot::Early(result));
+        }
+        waiting
+    };
+    let Some(loading) = loading else {
+        return FMOD_RESULT::FMOD_OK;
+    };
+
+    let has_blocks = {
+        let mut state = loading.state.lock().unwrap();
+        if state.result.is_some() {
+            return FMOD_RESULT::FMOD_OK;
+        }
+        state.result = Some(result);
+        !state.on_ready.is_empty()
+    };
+    loading.ready.notify_all();
+
+    if has_blocks {
+        // FMOD's loading thread shouldn't wait on ruby, so this only goes through the queue when it's deferred
+        let queue = callback::Queue::System(loading.system);
+        let object = loading.clone();
+        let run = move |ruby: &magnus::Ruby| loading.run_on_ready(ruby);
+        if callback::is_deferred(queue) {
+            let _ = callback::defer(Some(queue), "sound_ready", &*object, run);
+        } else {
+            callback::process(move |ruby| {
+                if let Err(e) = run(ruby) {
+                    callback::report_error(ruby, e);
+                }
+            });
+        }
+    }
+    FMOD_RESULT::FMOD_OK
+}
+
+impl Loading {
+    fn result(&self) -> Option<FMOD_RESULT> {
+        self.state.lock().unwrap().result
+    }
+
+    fn error(&self) -> Option<magnus::Error> {
+        crate::error::check(self.result()?)
+            .err()
+            .map(crate::error::from_fmod)
+    }
+
+    // wrapping a sound that was released would put its dangling handle back in the storage
+    fn rb_sound(&self) -> Result<RbSound> {
+        if !crate::extern_struct_storage::contains(self.sound) {
+            return Err(crate::error::use_after_free(self.sound));
+        }
+        self.sound.into_ruby()
+    }
+
+    fn run_on_ready(&self, ruby: &magnus::Ruby) -> Result<()> {
+        // taken out here rather than in the nonblock callback so they stay marked until now
+        let blocks = std::mem::take(&mut self.state.lock().unwrap().on_ready);
+        let sound = self.rb_sound()?;
+        let error = self.error().and_then(|e| e.value());
+        for block in blocks {
+            let _: magnus::Value = block.get_inner_with(ruby).call((sound, error))?;
+        }
+        Ok(())
+    }
+
+    // returns whether the sound finished loading
+    fn wait(&self, deadline: Option<Instant>, interrupted: &AtomicBool) -> bool {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.result.is_some() {
+                return true;
+            }
+            if interrupted.load(Ordering::Acquire) {
+                return false;
+            }
+            state = match deadline {
+                None => self.ready.wait(state).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return false;
+                    }
+                    self.ready.wait_timeout(state, deadline - now).unwrap().0
+                }
+            };
+        }
+    }
+}
+
+/// Ruby handle for a sound created with `System#create_sound_async`.
+#[magnus::wrap(class = "FMOD::SoundFuture", free_immediately, size)]
+pub struct SoundFuture(Arc<Loading>);
+
+type RbSoundFuture = magnus::typed_data::Obj<SoundFuture>;
+
+impl SoundFuture {
+    pub fn new(loading: Arc<Loading>) -> Self {
+        Self(loading)
+    }
+
+    fn is_ready(&self) -> bool {
+        self.0.result().is_some()
+    }
+
+    fn sound(&self) -> Result<RbSound> {
+        self.0.rb_sound()
+    }
+
+    // blocks (without the GVL) until the sound is loaded, or `timeout` seconds pass. returns whether it loaded
+    fn wait(&self, timeout: Option<f64>) -> bool {
+        // a timeout too long to represent (like Float::INFINITY) is the same as none
+        let deadline = timeout
+            .and_then(|t| Duration::try_from_secs_f64(t.max(0.0)).ok())
+            .and_then(|timeout| Instant::now().checked_add(timeout));
+        let interrupted = AtomicBool::new(false);
+        let loading = &self.0;
+        unsafe {
+            thread::without_gvl(
+                || loading.wait(deadline, &interrupted),
+                || {
+                    interrupted.store(true, Ordering::Release);
+                    // take the lock so the waiting thread can't miss the notification
+                    drop(loading.state.lock().unwrap());
+                    loading.ready.notify_all();
+                },
+            )
+        }
+    }
+
+    // the sound, once it's loaded. raises the error FMOD loaded it with, if any
+    fn value(&self) -> Result<Option<RbSound>> {
+        if !self.is_ready() {
+            return Ok(None);
+        }
+        match self.0.error() {
+            Some(e) => Err(e),
+            None => self.sound().map(Some),
+        }
+    }
+
+    // on_ready { |sound, error| ... }
+    fn on_ready(ruby: &magnus::Ruby, rb_self: RbSoundFuture) -> Result<RbSoundFuture> {
+        let block = ruby.block_proc()?;
+        {
+            let mut state = rb_self.0.state.lock().unwrap();
+            if state.result.is_none() {
+                state.on_ready.push(block.into());
+                return Ok(rb_self);
+            }
+        }
+        let sound = rb_self.sound()?;
+        let error = rb_self.0.error().and_then(|e| e.value());
+        let _: magnus::Value = block.call((sound, error))?;
+        Ok(rb_self)
+    }
+}
+
+pub fn bind(module: magnus::RModule) -> Result<()> {
+    module.define_class("SoundLoading", magnus::class::basic_object())?;
+    module.ivar_set("__sound_loading", _SoundLoading)?;
+
+    let class = module.define_class("SoundFuture", magnus::class::object())?;
+    class.undef_default_alloc_func();
+    class.define_method("ready?", magnus::method!(SoundFuture::is_ready, 0))?;
+    class.define_method("sound", magnus::method!(SoundFuture::sound, 0))?;
+    class.define_method("__wait", magnus::method!(SoundFuture::wait, 1))?;
+    class.define_method("__value", magnus::method!(SoundFuture::value, 0))?;
+    class.define_method("on_ready", magnus::method!(SoundFuture::on_ready, 0))?;
+
+    Ok(())
+}
@@ -27,6 +27,19 @@ impl SoundGroup {
         group.release().into_ruby()
     }
 
+    fn set_owned_by_ruby(rb_self: RbSoundGroup, owned: bool) -> Result<()> {
+        use crate::FromRuby;
+        let group: fmod::SoundGroup = rb_self.from_ruby()?;
+        crate::extern_struct_storage::set_owned(group, rb_self, owned);
+        Ok(())
+    }
+
+    fn is_owned_by_ruby(rb_self: RbSoundGroup) -> Result<bool> {
+        use crate::FromRuby;
+        let group: fmod::SoundGroup = rb_self.from_ruby()?;
+        Ok(crate::extern_struct_storage::is_owned(group))
+    }
+
     fn get_userdata(rb_self: RbSoundGroup) -> Result<magnus::Value> {
         rb_self.ivar_get("__userdata")
     }
@@ -61,6 +74,8 @@ extern_struct_bind! {
     fn get_userdata -> 0;
     fn set_userdata -> 1;
     fn release -> 0;
+    fn set_owned_by_ruby -> 1;
+    fn is_owned_by_ruby -> 0;
     fn get_system -> 0;
     fn set_max_audible -> 1;
     fn get_max_audible -> 0;
@@ -8,6 +8,8 @@ use magnus::{prelude::*, value::InnerValue, IntoValue};
 
 use crate::ruby_struct;
 
+use super::enums::{SoundFormat, SoundType};
+
 ruby_struct! {
   struct Guid: fmod::Guid {
     data_1: u32,
@@ -71,6 +73,23 @@ ruby_struct! {
   }
 }
 
+/// What [`fmod::Sound::get_format`] returns, with names.
+pub struct FormatInfo {
+    pub sound_type: fmod::SoundType,
+    pub format: fmod::SoundFormat,
+    pub channels: i32,
+    pub bits: i32,
+}
+
+ruby_struct! {
+  struct SoundFormatInfo: FormatInfo {
+    sound_type: SoundType,
+    format: SoundFormat,
+    channels: i32,
+    bits: i32,
+  }
+}
+
 pub type Tag = magnus::RStruct;
 
 const _: () = {
@@ -112,6 +131,130 @@ const _: () = {
     }
 };
 
+pub type DspParameterInfo = magnus::RStruct;
+
+const _: () = {
+    use fmod::ffi::{FMOD_DSP_PARAMETER_DESC, FMOD_DSP_PARAMETER_TYPE};
+    use std::ffi::{c_char, CStr};
+
+    static CLASS: once_cell::sync::OnceCell<magnus::value::Opaque<magnus::RClass>> =
+        once_cell::sync::OnceCell::new();
+
+    fn string(ptr: *const c_char) -> Option<String> {
+        if ptr.is_null() {
+            return None;
+        }
+        Some(
+            unsafe { CStr::from_ptr(ptr) }
+                .to_string_lossy()
+                .into_owned(),
+        )
+    }
+
+    fn strings(ptr: *const *const c_char, count: usize) -> Option<Vec<String>> {
+        if ptr.is_null() {
+            return None;
+        }
+        let ptrs = unsafe { std::slice::from_raw_parts(ptr, count) };
+        ptrs.iter().map(|&ptr| string(ptr)).collect()
+    }
+
+    impl IntoRuby<DspParameterInfo> for &FMOD_DSP_PARAMETER_DESC {
+        fn into_ruby(self) -> Result<DspParameterInfo> {
+            let ruby = magnus::Ruby::get().unwrap();
+            let nil = || ruby.qnil().as_value();
+            let name = string(self.name.as_ptr()).unwrap_or_default();
+            let label = string(self.label.as_ptr()).unwrap_or_default();
+            let description = string(self.description);
+
+            let (min, max, default, value_names, data_type) = unsafe {
+                let desc = &self.__bindgen_anon_1;
+                match self.type_ {
+                    FMOD_DSP_PARAMETER_TYPE::FMOD_DSP_PARAMETER_TYPE_FLOAT => {
+                        let desc = desc.floatdesc;
+                        let (min, max, default) = (desc.min, desc.max, desc.defaultval);
+                        (
+                            min.into_value(),
+                            max.into_value(),
+                            default.into_value(),
+                            nil(),
+                            nil(),
+                        )
+                    }
+                    FMOD_DSP_PARAMETER_TYPE::FMOD_DSP_PARAMETER_TYPE_INT => {
+                        let desc = desc.intdesc;
+                        let count = (desc.max - desc.min + 1).max(0) as usize;
+                        let names = strings(desc.valuenames, count);
+                        (
+                            desc.min.into_value(),
+                            desc.max.into_value(),
+                            desc.defaultval.into_value(),
+                            names.into_value(),
+                            nil(),
+                        )
+                    }
+                    FMOD_DSP_PARAMETER_TYPE::FMOD_DSP_PARAMETER_TYPE_BOOL => {
+                        let desc = desc.booldesc;
+                        let names = strings(desc.valuenames, 2);
+                        let default = desc.defaultval != 0;
+                        (
+                            nil(),
+                            nil(),
+                            default.into_value(),
+                            names.into_value(),
+                            nil(),
+                        )
+                    }
+                    _ => {
+                        let data_type = desc.datadesc.datatype;
+                        (nil(), nil(), nil(), nil(), data_type.into_value())
+                    }
+                }
+            };
+
+            let rstruct = FMOD_DSP_PARAMETER_DESC::class().new_instance((
+                self.type_ as i32,
+                name,
+                label,
+                description,
+                min,
+                max,
+                default,
+                value_names,
+                data_type,
+            ))?;
+            DspParameterInfo::try_convert(rstruct)
+        }
+    }
+
+    impl Bindable for FMOD_DSP_PARAMETER_DESC {
+        fn bind(module: impl magnus::Module) -> Result<()> {
+            let rstruct = magnus::r_struct::define_struct(
+                Some("DspParameterInfo"),
+                (
+                    "type",
+                    "name",
+                    "label",
+                    "description",
+                    "min",
+                    "max",
+                    "default",
+                    "value_names",
+                    "data_type",
+                ),
+            )?;
+            let _ = CLASS.set(rstruct.into());
+            module.const_set("DspParameterInfo", rstruct)
+        }
+
+        #[allow(refining_impl_trait)]
+        fn class() -> magnus::RClass {
+            let ruby = magnus::Ruby::get().unwrap();
+            CLASS.get().unwrap().get_inner_with(&ruby)
+        }
+    }
+};
+
 pub type ErrorCallbackInfo = magnus::RStruct;
 
 const _: () = {
@@ -176,7 +319,9 @@ pub fn bind(module: magnus::RModule) -> Result<()> {
     fmod::CpuUsage::bind(module)?;
     fmod::ReverbProperties::bind(module)?;
     fmod::DspMeteringInfo::bind(module)?;
+    FormatInfo::bind(module)?;
     fmod::Tag::bind(module)?;
+    fmod::ffi::FMOD_DSP_PARAMETER_DESC::bind(module)?;
 
     let class = fmod::ReverbProperties::class();
     let module: magnus::RModule = class.define_module("Presets")?;
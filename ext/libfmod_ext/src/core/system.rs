@@ -19,6 +19,7 @@ use super::{
     rolloff_callback::RolloffCallback,
     sound::RbSound,
     sound_builder::SoundBuilder,
+    sound_future::SoundFuture,
     sound_group::RbSoundGroup,
     structs::{CPUUsage, Guid, ReverbProperties, Vector},
     system_builder::SystemBuilder,
@@ -37,14 +38,20 @@ impl System {
     fn update(rb_self: RbSystem) -> Result<()> {
         let system: fmod::System = rb_self.from_ruby()?;
         unsafe { thread::without_gvl_no_ubf(|| system.update()) }.into_ruby()?;
-        crate::extern_struct_storage::cleanup();
-        Ok(())
+        // run deferred callbacks before cleanup so the handles they reference are still around
+        crate::callback::drain(crate::callback::Queue::System(system));
+        crate::extern_struct_storage::cleanup_step();
+        crate::callback::raise_pending_error()
     }
 
     fn release(rb_self: RbSystem) -> Result<()> {
         let system: fmod::System = rb_self.from_ruby()?;
-        unsafe { system.release() }.into_ruby()?;
-        crate::extern_struct_storage::remove(system);
+        // releasing waits for the mixer, which may be waiting on ruby (for a custom DSP's block)
+        unsafe { thread::without_gvl_no_ubf(|| system.release()) }.into_ruby()?;
+        crate::callback::discard(crate::callback::Queue::System(system));
+        // everything created through this system is gone now too
+        crate::extern_struct_storage::remove_with_children(system);
+        super::file_system::forget(system);
         crate::extern_struct_storage::cleanup();
         Ok(())
     }
@@ -54,22 +61,88 @@ impl System {
         unsafe { thread::without_gvl_no_ubf(|| system.lock_dsp()) }.into_ruby()
     }
 
-    fn create_sound(rb_self: RbSystem, builder: &SoundBuilder) -> Result<RbSound> {
+    fn create_sound_with(
+        rb_self: RbSystem,
+        builder: &SoundBuilder,
+        create: impl FnOnce(fmod::System, &fmod::SoundBuilder<'static>) -> fmod::Result<fmod::Sound>,
+    ) -> Result<RbSound> {
         let system: fmod::System = rb_self.from_ruby()?;
+        let _scope = crate::extern_struct_storage::OwnerScope::enter(system);
+        let data = builder.1.clone();
+        let source = builder.2.clone();
+        let pcm = builder.3.clone();
         let borrow = builder.0.borrow();
         let builder = borrow
             .as_ref()
             .ok_or_else(SoundBuilder::invalid_state_error)?;
-        unsafe { thread::without_gvl_no_ubf(|| system.create_sound(builder)) }.into_ruby()
+
+        let sound = unsafe { thread::without_gvl_no_ubf(|| create(system, builder)) };
+        if let (Ok(sound), Some(pcm)) = (&sound, &pcm) {
+            super::pcm::attach(*sound, pcm);
+        }
+        let sound: RbSound = sound.into_ruby()?;
+
+        // FMOD may still be reading from the builder's memory (or calling its PCM callback), so the sound holds on to
+        // it until it's released
+        if let Some(data) = data {
+            crate::extern_struct_storage::attach(sound.0, data);
+        }
+        if let Some(source) = source {
+            crate::extern_struct_storage::attach(sound.0, source);
+        }
+        if let Some(pcm) = pcm {
+            crate::extern_struct_storage::attach(sound.0, pcm);
+        }
+        Ok(sound)
+    }
+
+    fn create_sound(rb_self: RbSystem, builder: &SoundBuilder) -> Result<RbSound> {
+        Self::create_sound_with(rb_self, builder, |system, builder| {
+            system.create_sound(builder)
+        })
     }
 
     fn create_stream(rb_self: RbSystem, builder: &SoundBuilder) -> Result<RbSound> {
+        Self::create_sound_with(rb_self, builder, |system, builder| {
+            system.create_stream(builder)
+        })
+    }
+
+    // loads the sound in the background, FMOD_NONBLOCKING is added to the builder's mode for the call
+    fn create_sound_async(rb_self: RbSystem, builder: &SoundBuilder) -> Result<SoundFuture> {
         let system: fmod::System = rb_self.from_ruby()?;
-        let borrow = builder.0.borrow();
-        let builder = borrow
-            .as_ref()
+        let original = {
+            let mut borrow = builder.0.borrow_mut();
+            let inner = borrow
+                .take()
+                .ok_or_else(SoundBuilder::invalid_state_error)?;
+            let original = (inner.mode(), inner.raw_ex_info());
+            *borrow = Some(super::sound_future::prepare(inner));
+            original
+        };
+
+        let sound = Self::create_sound_with(rb_self, builder, |system, builder| {
+            system.create_sound(builder)
+        });
+
+        // put the builder back the way it was, so it can still be used normally
+        let mut borrow = builder.0.borrow_mut();
+        let inner = borrow
+            .take()
             .ok_or_else(SoundBuilder::invalid_state_error)?;
-        unsafe { thread::without_gvl_no_ubf(|| system.create_stream(builder)) }.into_ruby()
+        let (mode, ex_info) = original;
+        *borrow = Some(unsafe { inner.with_mode(mode).with_raw_ex_info(ex_info) });
+        drop(borrow);
+
+        let sound: fmod::Sound = sound?.from_ruby()?;
+        let loading = super::sound_future::register(system, sound);
+        crate::extern_struct_storage::attach(sound, loading.clone());
+        Ok(SoundFuture::new(loading))
+    }
+
+    // create_custom_dsp(name:, channels: nil, parameters: [], processor: nil) { |in_buf, out_buf, length, channels, parameters| ... }
+    fn create_custom_dsp(rb_self: RbSystem, args: &[magnus::Value]) -> Result<RbDSP> {
+        super::custom_dsp::create(rb_self, args)
     }
 
     fn get_userdata(rb_self: RbSystem) -> Result<magnus::Value> {
@@ -99,9 +172,47 @@ impl System {
         }
 
         rb_self.ivar_set("__callback", callback)?;
+        rb_self.ivar_set("__callback_mask", u32::from(mask))?;
         system.set_callback::<SystemCallback>(mask).into_ruby()
     }
 
+    fn set_file_system(rb_self: RbSystem, opener: Option<magnus::Value>) -> Result<()> {
+        let system: fmod::System = rb_self.from_ruby()?;
+        super::file_system::set(system, opener)
+    }
+
+    fn apply_callback_mask(rb_self: magnus::Value, mask: u32) -> Result<()> {
+        let rb_self: RbSystem = magnus::TryConvert::try_convert(rb_self)?;
+        let system: fmod::System = rb_self.from_ruby()?;
+        system
+            .set_callback::<SystemCallback>(mask.into())
+            .into_ruby()
+    }
+
+    fn on(rb_self: RbSystem, event: magnus::Symbol) -> Result<magnus::Value> {
+        let ruby = magnus::Ruby::get().unwrap();
+        crate::listeners::subscribe(
+            rb_self,
+            event,
+            ruby.block_proc()?,
+            super::system_callback::EVENTS,
+            Self::apply_callback_mask,
+        )
+    }
+
+    fn set_deferred_callbacks(rb_self: RbSystem, deferred: bool) -> Result<()> {
+        let system: fmod::System = rb_self.from_ruby()?;
+        crate::callback::set_deferred(crate::callback::Queue::System(system), deferred);
+        Ok(())
+    }
+
+    fn get_deferred_callbacks(rb_self: RbSystem) -> Result<bool> {
+        let system: fmod::System = rb_self.from_ruby()?;
+        Ok(crate::callback::is_deferred(
+            crate::callback::Queue::System(system),
+        ))
+    }
+
     fn set_3d_rolloff_callback(rb_self: RbSystem, callback: magnus::Value) -> Result<()> {
         let system: fmod::System = rb_self.from_ruby()?;
 
@@ -199,9 +310,15 @@ extern_struct_fns! {
 extern_struct_bind! {
   impl Bindable for System: fmod::System {
     fn set_callback -> 2;
+    fn set_file_system -> 1;
+    fn on -> 1;
+    fn set_deferred_callbacks -> 1;
+    fn get_deferred_callbacks -> 0;
     fn create_sound -> 1;
     fn create_stream -> 1;
+    fn create_sound_async -> 1;
     fn create_dsp_by_type -> 1;
+    fn create_custom_dsp -> -1;
     fn create_channel_group -> 1;
     fn create_sound_group -> 1;
     fn create_reverb_3d -> 0;
@@ -3,7 +3,7 @@
 // This Source Code Form is subject to the terms of the Mozilla Public
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
-use crate::{callback, IntoRuby, Result};
+use crate::{callback, listeners, IntoRuby, Result};
 use magnus::{
     prelude::*,
     value::{InnerValue, Opaque},
@@ -14,35 +14,90 @@ use super::{enums::OutputType, sound::RbSound, structs::ErrorCallbackInfo, syste
 
 static CLASS: OnceCell<Opaque<magnus::RClass>> = OnceCell::new();
 
+/// Events that can be listened to with `System#on`.
+pub const EVENTS: listeners::Events = &[
+    (
+        "device_list_changed",
+        fmod::ffi::FMOD_SYSTEM_CALLBACK_DEVICELISTCHANGED,
+    ),
+    ("device_lost", fmod::ffi::FMOD_SYSTEM_CALLBACK_DEVICELOST),
+    (
+        "memory_allocation_failed",
+        fmod::ffi::FMOD_SYSTEM_CALLBACK_MEMORYALLOCATIONFAILED,
+    ),
+    (
+        "thread_created",
+        fmod::ffi::FMOD_SYSTEM_CALLBACK_THREADCREATED,
+    ),
+    (
+        "bad_dsp_connection",
+        fmod::ffi::FMOD_SYSTEM_CALLBACK_BADDSPCONNECTION,
+    ),
+    ("premix", fmod::ffi::FMOD_SYSTEM_CALLBACK_PREMIX),
+    ("postmix", fmod::ffi::FMOD_SYSTEM_CALLBACK_POSTMIX),
+    ("error", fmod::ffi::FMOD_SYSTEM_CALLBACK_ERROR),
+    ("mid_mix", fmod::ffi::FMOD_SYSTEM_CALLBACK_MIDMIX),
+    (
+        "thread_destroyed",
+        fmod::ffi::FMOD_SYSTEM_CALLBACK_THREADDESTROYED,
+    ),
+    ("pre_update", fmod::ffi::FMOD_SYSTEM_CALLBACK_PREUPDATE),
+    ("post_update", fmod::ffi::FMOD_SYSTEM_CALLBACK_POSTUPDATE),
+    (
+        "record_list_changed",
+        fmod::ffi::FMOD_SYSTEM_CALLBACK_RECORDLISTCHANGED,
+    ),
+    (
+        "buffered_no_mix",
+        fmod::ffi::FMOD_SYSTEM_CALLBACK_BUFFEREDNOMIX,
+    ),
+    (
+        "device_reinitialize",
+        fmod::ffi::FMOD_SYSTEM_CALLBACK_DEVICEREINITIALIZE,
+    ),
+    (
+        "output_underrun",
+        fmod::ffi::FMOD_SYSTEM_CALLBACK_OUTPUTUNDERRUN,
+    ),
+    (
+        "record_position_changed",
+        fmod::ffi::FMOD_SYSTEM_CALLBACK_RECORDPOSITIONCHANGED,
+    ),
+];
+
 pub struct SystemCallback;
 
 impl fmod::SystemCallback for SystemCallback {
     fn device_list_changed(system: fmod::System, _: *mut std::ffi::c_void) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback
-                .funcall("device_list_changed", (system, userdata))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            Some(callback::Queue::System(system)),
+            "device_list_changed",
+            &system,
+            move |_| {
+                let system = system.into_ruby()?;
+                let userdata: magnus::Value = system.ivar_get("__userdata")?;
+                let callback: magnus::Value = system.ivar_get("__callback")?;
+                let _: magnus::Value =
+                    listeners::dispatch(callback, "device_list_changed", (system, userdata))?;
+                Ok(())
+            },
+        )
     }
 
     fn device_lost(system: fmod::System, _: *mut std::ffi::c_void) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback.funcall("device_lost", (system, userdata)).unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            Some(callback::Queue::System(system)),
+            "device_lost",
+            &system,
+            move |_| {
+                let system = system.into_ruby()?;
+                let userdata: magnus::Value = system.ivar_get("__userdata")?;
+                let callback: magnus::Value = system.ivar_get("__callback")?;
+                let _: magnus::Value =
+                    listeners::dispatch(callback, "device_lost", (system, userdata))?;
+                Ok(())
+            },
+        )
     }
 
     fn memory_allocation_failed(
@@ -51,20 +106,24 @@ impl fmod::SystemCallback for SystemCallback {
         size: std::ffi::c_int,
         _: *mut std::ffi::c_void,
     ) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
         let file = file.to_cstring();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let file = file.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback
-                .funcall("memory_allocation_failed", (system, file, size, userdata))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            Some(callback::Queue::System(system)),
+            "memory_allocation_failed",
+            &system,
+            move |_| {
+                let system = system.into_ruby()?;
+                let file = file.into_ruby()?;
+                let userdata: magnus::Value = system.ivar_get("__userdata")?;
+                let callback: magnus::Value = system.ivar_get("__callback")?;
+                let _: magnus::Value = listeners::dispatch(
+                    callback,
+                    "memory_allocation_failed",
+                    (system, file, size, userdata),
+                )?;
+                Ok(())
+            },
+        )
     }
 
     fn thread_created(
@@ -73,61 +132,71 @@ impl fmod::SystemCallback for SystemCallback {
         thread_name: &fmod::Utf8CStr,
         _: *mut std::ffi::c_void,
     ) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
         let thread_name = thread_name.to_cstring();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let thread_name = thread_name.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback
-                .funcall("thread_created", (system, thread_name, userdata))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            Some(callback::Queue::System(system)),
+            "thread_created",
+            &system,
+            move |_| {
+                let system = system.into_ruby()?;
+                let thread_name = thread_name.into_ruby()?;
+                let userdata: magnus::Value = system.ivar_get("__userdata")?;
+                let callback: magnus::Value = system.ivar_get("__callback")?;
+                let _: magnus::Value = listeners::dispatch(
+                    callback,
+                    "thread_created",
+                    (system, thread_name, userdata),
+                )?;
+                Ok(())
+            },
+        )
     }
 
     fn bad_dsp_connection(system: fmod::System, _: *mut std::ffi::c_void) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback
-                .funcall("bad_dsp_connection", (system, userdata))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            Some(callback::Queue::System(system)),
+            "bad_dsp_connection",
+            &system,
+            move |_| {
+                let system = system.into_ruby()?;
+                let userdata: magnus::Value = system.ivar_get("__userdata")?;
+                let callback: magnus::Value = system.ivar_get("__callback")?;
+                let _: magnus::Value =
+                    listeners::dispatch(callback, "bad_dsp_connection", (system, userdata))?;
+                Ok(())
+            },
+        )
     }
 
     fn premix(system: fmod::System, _: *mut std::ffi::c_void) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback.funcall("premix", (system, userdata)).unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            Some(callback::Queue::System(system)),
+            "premix",
+            &system,
+            move |_| {
+                let system = system.into_ruby()?;
+                let userdata: magnus::Value = system.ivar_get("__userdata")?;
+                let callback: magnus::Value = system.ivar_get("__callback")?;
+                let _: magnus::Value = listeners::dispatch(callback, "premix", (system, userdata))?;
+                Ok(())
+            },
+        )
     }
 
     fn postmix(system: fmod::System, _: *mut std::ffi::c_void) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback.funcall("postmix", (system, userdata)).unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            Some(callback::Queue::System(system)),
+            "postmix",
+            &system,
+            move |_| {
+                let system = system.into_ruby()?;
+                let userdata: magnus::Value = system.ivar_get("__userdata")?;
+                let callback: magnus::Value = system.ivar_get("__callback")?;
+                let _: magnus::Value =
+                    listeners::dispatch(callback, "postmix", (system, userdata))?;
+                Ok(())
+            },
+        )
     }
 
     fn error(
@@ -139,32 +208,31 @@ impl fmod::SystemCallback for SystemCallback {
         // Therefore, the callback can't use error_info for longer than its lifetime
         let error_info: fmod::ErrorCallbackInfo<'static> =
             unsafe { std::mem::transmute(error_info) };
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let error_info = error_info.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback
-                .funcall("error", (system, error_info, userdata))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::call_blocking("error", &system, move |_| {
+            let system = system.into_ruby()?;
+            let error_info = error_info.into_ruby()?;
+            let userdata: magnus::Value = system.ivar_get("__userdata")?;
+            let callback: magnus::Value = system.ivar_get("__callback")?;
+            let _: magnus::Value =
+                listeners::dispatch(callback, "error", (system, error_info, userdata))?;
+            Ok(())
+        })
     }
 
     fn mid_mix(system: fmod::System, _: *mut std::ffi::c_void) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback.funcall("mid_mix", (system, userdata)).unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            Some(callback::Queue::System(system)),
+            "mid_mix",
+            &system,
+            move |_| {
+                let system = system.into_ruby()?;
+                let userdata: magnus::Value = system.ivar_get("__userdata")?;
+                let callback: magnus::Value = system.ivar_get("__callback")?;
+                let _: magnus::Value =
+                    listeners::dispatch(callback, "mid_mix", (system, userdata))?;
+                Ok(())
+            },
+        )
     }
 
     fn thread_destroyed(
@@ -173,76 +241,88 @@ impl fmod::SystemCallback for SystemCallback {
         thread_name: &fmod::Utf8CStr,
         _: *mut std::ffi::c_void,
     ) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
         let thread_name = thread_name.to_cstring();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let thread_name = thread_name.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback
-                .funcall("thread_destroyed", (system, thread_name, userdata))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            Some(callback::Queue::System(system)),
+            "thread_destroyed",
+            &system,
+            move |_| {
+                let system = system.into_ruby()?;
+                let thread_name = thread_name.into_ruby()?;
+                let userdata: magnus::Value = system.ivar_get("__userdata")?;
+                let callback: magnus::Value = system.ivar_get("__callback")?;
+                let _: magnus::Value = listeners::dispatch(
+                    callback,
+                    "thread_destroyed",
+                    (system, thread_name, userdata),
+                )?;
+                Ok(())
+            },
+        )
     }
 
     fn pre_update(system: fmod::System, _: *mut std::ffi::c_void) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback.funcall("pre_update", (system, userdata)).unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            Some(callback::Queue::System(system)),
+            "pre_update",
+            &system,
+            move |_| {
+                let system = system.into_ruby()?;
+                let userdata: magnus::Value = system.ivar_get("__userdata")?;
+                let callback: magnus::Value = system.ivar_get("__callback")?;
+                let _: magnus::Value =
+                    listeners::dispatch(callback, "pre_update", (system, userdata))?;
+                Ok(())
+            },
+        )
     }
 
     fn post_update(system: fmod::System, _: *mut std::ffi::c_void) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback.funcall("post_update", (system, userdata)).unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            Some(callback::Queue::System(system)),
+            "post_update",
+            &system,
+            move |_| {
+                let system = system.into_ruby()?;
+                let userdata: magnus::Value = system.ivar_get("__userdata")?;
+                let callback: magnus::Value = system.ivar_get("__callback")?;
+                let _: magnus::Value =
+                    listeners::dispatch(callback, "post_update", (system, userdata))?;
+                Ok(())
+            },
+        )
     }
 
     fn record_list_changed(system: fmod::System, _: *mut std::ffi::c_void) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback
-                .funcall("record_list_changed", (system, userdata))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            Some(callback::Queue::System(system)),
+            "record_list_changed",
+            &system,
+            move |_| {
+                let system = system.into_ruby()?;
+                let userdata: magnus::Value = system.ivar_get("__userdata")?;
+                let callback: magnus::Value = system.ivar_get("__callback")?;
+                let _: magnus::Value =
+                    listeners::dispatch(callback, "record_list_changed", (system, userdata))?;
+                Ok(())
+            },
+        )
     }
 
     fn buffered_no_mix(system: fmod::System, _: *mut std::ffi::c_void) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback
-                .funcall("buffered_no_mix", (system, userdata))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            Some(callback::Queue::System(system)),
+            "buffered_no_mix",
+            &system,
+            move |_| {
+                let system = system.into_ruby()?;
+                let userdata: magnus::Value = system.ivar_get("__userdata")?;
+                let callback: magnus::Value = system.ivar_get("__callback")?;
+                let _: magnus::Value =
+                    listeners::dispatch(callback, "buffered_no_mix", (system, userdata))?;
+                Ok(())
+            },
+        )
     }
 
     fn device_reinitialize(
@@ -251,37 +331,39 @@ impl fmod::SystemCallback for SystemCallback {
         driver_index: std::ffi::c_int,
         _: *mut std::ffi::c_void,
     ) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let output_type = output_type.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback
-                .funcall(
+        callback::defer(
+            Some(callback::Queue::System(system)),
+            "device_reinitialize",
+            &system,
+            move |_| {
+                let system = system.into_ruby()?;
+                let output_type = output_type.into_ruby()?;
+                let userdata: magnus::Value = system.ivar_get("__userdata")?;
+                let callback: magnus::Value = system.ivar_get("__callback")?;
+                let _: magnus::Value = listeners::dispatch(
+                    callback,
                     "device_reinitialize",
                     (system, output_type, driver_index, userdata),
-                )
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+                )?;
+                Ok(())
+            },
+        )
     }
 
     fn output_underrun(system: fmod::System, _: *mut std::ffi::c_void) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback
-                .funcall("output_underrun", (system, userdata))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            Some(callback::Queue::System(system)),
+            "output_underrun",
+            &system,
+            move |_| {
+                let system = system.into_ruby()?;
+                let userdata: magnus::Value = system.ivar_get("__userdata")?;
+                let callback: magnus::Value = system.ivar_get("__callback")?;
+                let _: magnus::Value =
+                    listeners::dispatch(callback, "output_underrun", (system, userdata))?;
+                Ok(())
+            },
+        )
     }
 
     fn record_position_changed(
@@ -290,22 +372,23 @@ impl fmod::SystemCallback for SystemCallback {
         record_position: std::ffi::c_int,
         _: *mut std::ffi::c_void,
     ) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let sound = sound.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback
-                .funcall(
+        callback::defer(
+            Some(callback::Queue::System(system)),
+            "record_position_changed",
+            &system,
+            move |_| {
+                let system = system.into_ruby()?;
+                let sound = sound.into_ruby()?;
+                let userdata: magnus::Value = system.ivar_get("__userdata")?;
+                let callback: magnus::Value = system.ivar_get("__callback")?;
+                let _: magnus::Value = listeners::dispatch(
+                    callback,
                     "record_position_changed",
                     (system, sound, record_position, userdata),
-                )
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+                )?;
+                Ok(())
+            },
+        )
     }
 }
 
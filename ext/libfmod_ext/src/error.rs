@@ -5,34 +5,293 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use crate::Result;
+use fmod::ffi::FMOD_RESULT;
 use magnus::{
     prelude::*,
     value::{InnerValue, Opaque},
 };
 use once_cell::sync::OnceCell;
+use std::collections::HashMap;
 
 static CLASS: OnceCell<Opaque<magnus::ExceptionClass>> = OnceCell::new();
+static USE_AFTER_FREE_CLASS: OnceCell<Opaque<magnus::ExceptionClass>> = OnceCell::new();
+// indexed the same as RESULTS
+static RESULT_CLASSES: OnceCell<Vec<Opaque<magnus::ExceptionClass>>> = OnceCell::new();
+
+struct ResultInfo {
+    result: FMOD_RESULT,
+    code: i32,
+    name: &'static str,
+    class_name: &'static str,
+    superclass_name: &'static str,
+}
+
+// intermediate classes that group related results together. they all inherit from FMOD::Error
+const CATEGORIES: &[&str] = &[
+    "ChannelError",
+    "DSPError",
+    "FileError",
+    "FormatError",
+    "HandleError",
+    "InitializationError",
+    "MemoryError",
+    "NetworkError",
+    "OutputError",
+    "ParameterError",
+    "PluginError",
+    "RecordError",
+    "ReverbError",
+    "StateError",
+    "StudioError",
+];
+
+macro_rules! fmod_results {
+    ($( $result:ident => $class_name:ident < $superclass_name:ident ),* $(,)?) => {
+        const RESULTS: &[ResultInfo] = &[
+            $(
+              ResultInfo {
+                  result: FMOD_RESULT::$result,
+                  code: FMOD_RESULT::$result as i32,
+                  name: stringify!($result),
+                  class_name: stringify!($class_name),
+                  superclass_name: stringify!($superclass_name),
+              },
+            )*
+        ];
+    };
+}
+
+fmod_results! {
+    FMOD_ERR_BADCOMMAND => BadCommandError < StateError,
+    FMOD_ERR_CHANNEL_ALLOC => ChannelAllocError < ChannelError,
+    FMOD_ERR_CHANNEL_STOLEN => ChannelStolenError < ChannelError,
+    FMOD_ERR_DMA => DMAError < OutputError,
+    FMOD_ERR_DSP_CONNECTION => DSPConnectionError < DSPError,
+    FMOD_ERR_DSP_DONTPROCESS => DSPDontProcessError < DSPError,
+    FMOD_ERR_DSP_FORMAT => DSPFormatError < DSPError,
+    FMOD_ERR_DSP_INUSE => DSPInUseError < DSPError,
+    FMOD_ERR_DSP_NOTFOUND => DSPNotFoundError < DSPError,
+    FMOD_ERR_DSP_RESERVED => DSPReservedError < DSPError,
+    FMOD_ERR_DSP_SILENCE => DSPSilenceError < DSPError,
+    FMOD_ERR_DSP_TYPE => DSPTypeError < DSPError,
+    FMOD_ERR_FILE_BAD => FileBadError < FileError,
+    FMOD_ERR_FILE_COULDNOTSEEK => FileCouldNotSeekError < FileError,
+    FMOD_ERR_FILE_DISKEJECTED => FileDiskEjectedError < FileError,
+    FMOD_ERR_FILE_EOF => FileEOFError < FileError,
+    FMOD_ERR_FILE_ENDOFDATA => FileEndOfDataError < FileError,
+    FMOD_ERR_FILE_NOTFOUND => FileNotFoundError < FileError,
+    FMOD_ERR_FORMAT => BadFormatError < FormatError,
+    FMOD_ERR_HEADER_MISMATCH => HeaderMismatchError < FormatError,
+    FMOD_ERR_HTTP => HTTPError < NetworkError,
+    FMOD_ERR_HTTP_ACCESS => HTTPAccessError < NetworkError,
+    FMOD_ERR_HTTP_PROXY_AUTH => HTTPProxyAuthError < NetworkError,
+    FMOD_ERR_HTTP_SERVER_ERROR => HTTPServerError < NetworkError,
+    FMOD_ERR_HTTP_TIMEOUT => HTTPTimeoutError < NetworkError,
+    FMOD_ERR_INITIALIZATION => BadInitializationError < InitializationError,
+    FMOD_ERR_INITIALIZED => AlreadyInitializedError < InitializationError,
+    FMOD_ERR_INTERNAL => InternalError < Error,
+    FMOD_ERR_INVALID_FLOAT => InvalidFloatError < ParameterError,
+    FMOD_ERR_INVALID_HANDLE => InvalidHandleError < HandleError,
+    FMOD_ERR_INVALID_PARAM => InvalidParamError < ParameterError,
+    FMOD_ERR_INVALID_POSITION => InvalidPositionError < ParameterError,
+    FMOD_ERR_INVALID_SPEAKER => InvalidSpeakerError < ParameterError,
+    FMOD_ERR_INVALID_SYNCPOINT => InvalidSyncPointError < ParameterError,
+    FMOD_ERR_INVALID_THREAD => InvalidThreadError < StateError,
+    FMOD_ERR_INVALID_VECTOR => InvalidVectorError < ParameterError,
+    FMOD_ERR_MAXAUDIBLE => MaxAudibleError < ChannelError,
+    FMOD_ERR_MEMORY => OutOfMemoryError < MemoryError,
+    FMOD_ERR_MEMORY_CANTPOINT => MemoryCantPointError < MemoryError,
+    FMOD_ERR_NEEDS3D => Needs3DError < ParameterError,
+    FMOD_ERR_NEEDSHARDWARE => NeedsHardwareError < OutputError,
+    FMOD_ERR_NET_CONNECT => NetConnectError < NetworkError,
+    FMOD_ERR_NET_SOCKET_ERROR => NetSocketError < NetworkError,
+    FMOD_ERR_NET_URL => NetURLError < NetworkError,
+    FMOD_ERR_NET_WOULD_BLOCK => NetWouldBlockError < NetworkError,
+    FMOD_ERR_NOTREADY => NotReadyError < StateError,
+    FMOD_ERR_OUTPUT_ALLOCATED => OutputAllocatedError < OutputError,
+    FMOD_ERR_OUTPUT_CREATEBUFFER => OutputCreateBufferError < OutputError,
+    FMOD_ERR_OUTPUT_DRIVERCALL => OutputDriverCallError < OutputError,
+    FMOD_ERR_OUTPUT_FORMAT => OutputFormatError < OutputError,
+    FMOD_ERR_OUTPUT_INIT => OutputInitError < OutputError,
+    FMOD_ERR_OUTPUT_NODRIVERS => OutputNoDriversError < OutputError,
+    FMOD_ERR_PLUGIN => PluginFailedError < PluginError,
+    FMOD_ERR_PLUGIN_MISSING => PluginMissingError < PluginError,
+    FMOD_ERR_PLUGIN_RESOURCE => PluginResourceError < PluginError,
+    FMOD_ERR_PLUGIN_VERSION => PluginVersionError < PluginError,
+    FMOD_ERR_RECORD => RecordFailedError < RecordError,
+    FMOD_ERR_REVERB_CHANNELGROUP => ReverbChannelGroupError < ReverbError,
+    FMOD_ERR_REVERB_INSTANCE => ReverbInstanceError < ReverbError,
+    FMOD_ERR_SUBSOUNDS => SubsoundsError < FormatError,
+    FMOD_ERR_SUBSOUND_ALLOCATED => SubsoundAllocatedError < FormatError,
+    FMOD_ERR_SUBSOUND_CANTMOVE => SubsoundCantMoveError < FormatError,
+    FMOD_ERR_TAGNOTFOUND => TagNotFoundError < FormatError,
+    FMOD_ERR_TOOMANYCHANNELS => TooManyChannelsError < ChannelError,
+    FMOD_ERR_TRUNCATED => TruncatedError < Error,
+    FMOD_ERR_UNIMPLEMENTED => UnimplementedError < Error,
+    FMOD_ERR_UNINITIALIZED => UninitializedError < InitializationError,
+    FMOD_ERR_UNSUPPORTED => UnsupportedError < Error,
+    FMOD_ERR_VERSION => VersionError < InitializationError,
+    FMOD_ERR_EVENT_ALREADY_LOADED => EventAlreadyLoadedError < StudioError,
+    FMOD_ERR_EVENT_LIVEUPDATE_BUSY => LiveUpdateBusyError < StudioError,
+    FMOD_ERR_EVENT_LIVEUPDATE_MISMATCH => LiveUpdateMismatchError < StudioError,
+    FMOD_ERR_EVENT_LIVEUPDATE_TIMEOUT => LiveUpdateTimeoutError < StudioError,
+    FMOD_ERR_EVENT_NOTFOUND => EventNotFoundError < StudioError,
+    FMOD_ERR_STUDIO_UNINITIALIZED => StudioUninitializedError < InitializationError,
+    FMOD_ERR_STUDIO_NOT_LOADED => StudioNotLoadedError < StudioError,
+    FMOD_ERR_INVALID_STRING => InvalidStringError < ParameterError,
+    FMOD_ERR_ALREADY_LOCKED => AlreadyLockedError < StateError,
+    FMOD_ERR_NOT_LOCKED => NotLockedError < StateError,
+    FMOD_ERR_RECORD_DISCONNECTED => RecordDisconnectedError < RecordError,
+    FMOD_ERR_TOOMANYSAMPLES => TooManySamplesError < ParameterError,
+}
 
 pub fn class() -> magnus::ExceptionClass {
     let ruby = magnus::Ruby::get().unwrap();
     CLASS.get().expect("class not set").get_inner_with(&ruby)
 }
 
+fn use_after_free_class() -> magnus::ExceptionClass {
+    let ruby = magnus::Ruby::get().unwrap();
+    USE_AFTER_FREE_CLASS
+        .get()
+        .expect("class not set")
+        .get_inner_with(&ruby)
+}
+
+fn result_class(index: usize) -> magnus::ExceptionClass {
+    let ruby = magnus::Ruby::get().unwrap();
+    RESULT_CLASSES.get().expect("classes not set")[index].get_inner_with(&ruby)
+}
+
+/// Turns the result of calling FMOD's C API directly into a `fmod::Result`.
+pub fn check(result: FMOD_RESULT) -> fmod::Result<()> {
+    match result {
+        FMOD_RESULT::FMOD_OK => Ok(()),
+        error => Err(fmod::Error::Fmod(error)),
+    }
+}
+
 pub fn use_after_free(v: impl std::fmt::Debug) -> magnus::Error {
-    magnus::Error::new(
-        class(),
-        format!("Use after free: {v:?} has already been released!"),
-    )
+    let message = format!("Use after free: {v:?} has already been released!");
+    let error = match use_after_free_class().new_instance((message,)) {
+        Ok(exception) => exception.into(),
+        Err(error) => return error,
+    };
+    match current_method_name() {
+        Some(method_name) => with_method_name(error, &method_name),
+        None => error,
+    }
+}
+
+pub fn exception_from_fmod(error: fmod::Error) -> Result<magnus::Exception> {
+    let message = error.to_string();
+    let index = match error {
+        fmod::Error::Fmod(result) => RESULTS.iter().position(|info| info.result == result),
+        _ => None,
+    };
+
+    let Some(index) = index else {
+        return class().new_instance((message,));
+    };
+
+    let info = &RESULTS[index];
+    let exception = result_class(index).new_instance((message,))?;
+    let object = magnus::RObject::from_value(exception.as_value()).unwrap();
+    object.ivar_set("@code", info.code)?;
+    object.ivar_set("@result_name", info.name)?;
+
+    Ok(exception)
 }
 
 pub fn from_fmod(error: fmod::Error) -> magnus::Error {
-    magnus::Error::new(class(), error.to_string())
+    let error = match exception_from_fmod(error) {
+        Ok(exception) => exception.into(),
+        Err(error) => return error,
+    };
+    match current_method_name() {
+        Some(method_name) => with_method_name(error, &method_name),
+        None => error,
+    }
+}
+
+// The ruby method being run, as `FMOD::Class#method` (or `FMOD::Module.method` for singleton methods).
+// This is the only place errors get their method name from.
+fn current_method_name() -> Option<String> {
+    use magnus::rb_sys::FromRawValue;
+
+    let id = unsafe { rb_sys::rb_frame_this_func() };
+    if id == 0 {
+        return None;
+    }
+    let name = unsafe { rb_sys::rb_id2name(id) };
+    if name.is_null() {
+        return None;
+    }
+    let name = unsafe { std::ffi::CStr::from_ptr(name) }.to_string_lossy();
+
+    // this raises when there's no frame (FMOD calling us on a ruby thread), hence the protect
+    let receiver = magnus::rb_sys::protect(|| unsafe { rb_sys::rb_current_receiver() }).ok()?;
+    let receiver = unsafe { magnus::Value::from_raw(receiver) };
+    // singleton methods are called on a class or module, so they're written `Class.method`
+    let singleton_owner = magnus::RModule::from_value(receiver)
+        .map(|module| module.inspect())
+        .or_else(|| magnus::RClass::from_value(receiver).map(|class| class.inspect()));
+    let (owner, separator) = match singleton_owner {
+        Some(owner) => (owner, "."),
+        None => (receiver.class().inspect(), "#"),
+    };
+    Some(format!("{owner}{separator}{name}"))
+}
+
+// Records which method raised an FMOD::Error, if it doesn't already have one.
+fn with_method_name(error: magnus::Error, method_name: &str) -> magnus::Error {
+    let Some(exception) = error.value() else {
+        return error;
+    };
+    if !exception.is_kind_of(class()) {
+        return error;
+    }
+    let Some(object) = magnus::RObject::from_value(exception) else {
+        return error;
+    };
+
+    let current: Option<magnus::RString> = object.ivar_get("@method_name").unwrap_or(None);
+    if current.is_none() {
+        let _ = object.ivar_set("@method_name", method_name);
+    }
+    error
 }
 
 pub fn bind(module: impl magnus::Module) -> Result<()> {
     let class = module.define_class("Error", magnus::exception::runtime_error().as_r_class())?;
+    class.define_attr("code", magnus::Attr::Read)?;
+    class.define_attr("result_name", magnus::Attr::Read)?;
+    class.define_attr("method_name", magnus::Attr::Read)?;
     let exception_class = magnus::ExceptionClass::from_value(class.as_value()).unwrap();
     let _ = CLASS.set(Opaque::from(exception_class));
 
+    let mut superclasses = HashMap::new();
+    superclasses.insert("Error", class);
+    for &name in CATEGORIES {
+        let category = module.define_class(name, class)?;
+        superclasses.insert(name, category);
+    }
+
+    let use_after_free = module.define_class("UseAfterFreeError", superclasses["HandleError"])?;
+    let use_after_free = magnus::ExceptionClass::from_value(use_after_free.as_value()).unwrap();
+    let _ = USE_AFTER_FREE_CLASS.set(Opaque::from(use_after_free));
+
+    let mut result_classes = Vec::with_capacity(RESULTS.len());
+    for info in RESULTS {
+        let result_class =
+            module.define_class(info.class_name, superclasses[info.superclass_name])?;
+        result_class.const_set("CODE", info.code)?;
+        result_class.const_set("RESULT_NAME", info.name)?;
+
+        let result_class = magnus::ExceptionClass::from_value(result_class.as_value()).unwrap();
+        result_classes.push(Opaque::from(result_class));
+    }
+    let _ = RESULT_CLASSES.set(result_classes);
+
     Ok(())
 }
@@ -11,16 +11,49 @@ use fmod::{
     ChannelControl, Dsp, DspConnection, Geometry, Reverb3D, Sound, SoundGroup, SyncPoint, System,
 };
 use once_cell::sync::Lazy;
-use std::{collections::HashMap, os::raw::c_void, sync::Mutex};
+use std::{
+    any::Any,
+    cell::Cell,
+    collections::{hash_map, HashMap, VecDeque},
+    os::raw::c_void,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Mutex, MutexGuard,
+    },
+};
 
 use magnus::prelude::*;
 use magnus::{error::Result, typed_data::Obj};
 
 #[derive(Debug, Default)]
 struct ExternStructStorage {
-    map: Mutex<HashMap<ExternStruct, magnus::RTypedData>>,
+    map: Mutex<HashMap<ExternStruct, Entry>>,
+    // handles waiting for a validity check, oldest first. only locked while `map` is held
+    sweep: Mutex<VecDeque<(ExternStruct, u64)>>,
+    next_stamp: AtomicU64,
+    // ruby owned handles that were garbage collected while `map` was locked, along with their wrapper's address
+    collected: Mutex<Vec<(ExternStruct, usize)>>,
+    // ruby owned handles that were garbage collected, and still need releasing
+    finalized: Mutex<Vec<(ExternStruct, Vec<Attachment>)>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    value: magnus::RTypedData,
+    // the system this was created through. releasing that system removes this entry
+    owner: Option<ExternStruct>,
+    // matches the sweep queue entry for this handle, so stale queue entries for a reused handle get skipped
+    stamp: u64,
+    // where this was created, when `ExternStructStorage.debug` was enabled at the time
+    backtrace: Option<Box<[String]>>,
+    // address of the wrapper when it's owned by ruby. these aren't marked, and are released once collected
+    owned: Option<usize>,
+    // things FMOD uses for this handle (like memory it reads from), dropped when this is removed
+    data: Vec<Attachment>,
 }
 
+type Attachment = Box<dyn Any + Send + Sync>;
+
 #[derive(PartialEq, Hash, Eq, Debug, Clone, Copy)]
 pub(crate) enum ExternStruct {
     StudioSystem(StudioSystem),
@@ -46,8 +79,12 @@ pub(crate) enum ExternStruct {
 unsafe impl Send for ExternStructStorage {}
 unsafe impl Sync for ExternStructStorage {}
 
-const DEFAULT_USERDATA_PTR: *mut c_void = 0xDEAD_CAFE as *mut c_void;
+pub(crate) const DEFAULT_USERDATA_PTR: *mut c_void = 0xDEAD_CAFE as *mut c_void;
 static STORAGE: Lazy<ExternStructStorage> = Lazy::new(Default::default);
+// how many handles `cleanup_step` checks per call
+static CLEANUP_BUDGET: AtomicUsize = AtomicUsize::new(256);
+// record a backtrace for every new handle
+static DEBUG: AtomicBool = AtomicBool::new(false);
 
 #[derive(magnus::TypedData)]
 #[magnus(class = "FMOD::ExternStructStorage")]
@@ -56,30 +93,265 @@ struct _ExternStructStorage;
 impl magnus::DataTypeFunctions for _ExternStructStorage {
     fn mark(&self, marker: &magnus::gc::Marker) {
         let storage = STORAGE.map.lock().unwrap();
-        for &value in storage.values() {
-            marker.mark(value);
+        for entry in storage.values().filter(|entry| entry.owned.is_none()) {
+            marker.mark(entry.value);
+        }
+    }
+}
+
+fn get_cleanup_budget() -> usize {
+    CLEANUP_BUDGET.load(Ordering::Relaxed)
+}
+
+fn set_cleanup_budget(budget: usize) {
+    CLEANUP_BUDGET.store(budget, Ordering::Relaxed);
+}
+
+fn get_debug() -> bool {
+    DEBUG.load(Ordering::Relaxed)
+}
+
+fn set_debug(debug: bool) {
+    DEBUG.store(debug, Ordering::Relaxed);
+}
+
+// has to be called before locking the storage, calling into ruby can trigger a GC which would mark the storage
+fn capture_backtrace() -> Option<Box<[String]>> {
+    if !get_debug() {
+        return None;
+    }
+    let ruby = magnus::Ruby::get().ok()?;
+    let backtrace: Vec<String> = ruby.module_kernel().funcall("caller", ()).ok()?;
+    Some(backtrace.into_boxed_slice())
+}
+
+fn stats() -> Result<magnus::RHash> {
+    let ruby = magnus::Ruby::get().unwrap();
+    let mut counts: Vec<(&'static str, usize)> = Vec::new();
+    {
+        let storage = lock();
+        for key in storage.keys() {
+            match counts.iter_mut().find(|(kind, _)| *kind == key.kind()) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((key.kind(), 1)),
+            }
         }
     }
+
+    let hash = ruby.hash_new();
+    for (kind, count) in counts {
+        hash.aset(ruby.to_symbol(kind), count)?;
+    }
+    Ok(hash)
+}
+
+// backs ExternStructStorage.each, which is defined in ruby so it can return an enumerator
+fn values(kind: Option<magnus::Symbol>) -> Result<magnus::RArray> {
+    let ruby = magnus::Ruby::get().unwrap();
+    let kind = kind.map(|k| k.name()).transpose()?;
+    let values = values_where(|key| kind.is_none() || kind.as_deref() == Some(key.kind()));
+    Ok(ruby.ary_from_iter(values))
+}
+
+/// Every live Sound, DSP and EventInstance, which all have to be released explicitly.
+fn leaks() -> Result<magnus::RArray> {
+    let ruby = magnus::Ruby::get().unwrap();
+    // anything FMOD already got rid of isn't a leak
+    cleanup();
+
+    let leaks: Vec<_> = {
+        let storage = lock();
+        storage
+            .iter()
+            // ruby owned handles get released when they're collected, so they don't count
+            .filter(|(key, entry)| {
+                entry.owned.is_none()
+                    && matches!(
+                        key,
+                        ExternStruct::Sound(_)
+                            | ExternStruct::Dsp(_)
+                            | ExternStruct::EventInstance(_)
+                    )
+            })
+            .map(|(key, entry)| (key.kind(), entry.value, entry.backtrace.clone()))
+            .collect()
+    };
+
+    let array = ruby.ary_new_capa(leaks.len());
+    for (kind, value, backtrace) in leaks {
+        let hash = ruby.hash_new();
+        hash.aset(ruby.to_symbol("type"), ruby.to_symbol(kind))?;
+        hash.aset(ruby.to_symbol("object"), value)?;
+        hash.aset(ruby.to_symbol("backtrace"), backtrace.map(Vec::from))?;
+        array.push(hash)?;
+    }
+    Ok(array)
 }
 
 pub fn bind(module: magnus::RModule) -> Result<()> {
     let class = module.define_class("ExternStructStorage", magnus::class::basic_object())?;
     class.ivar_set("__inst", _ExternStructStorage)?;
 
+    class.define_singleton_method("cleanup", magnus::function!(cleanup, 0))?;
+    class.define_singleton_method("cleanup_budget", magnus::function!(get_cleanup_budget, 0))?;
+    class.define_singleton_method("cleanup_budget=", magnus::function!(set_cleanup_budget, 1))?;
+    class.define_singleton_method("debug", magnus::function!(get_debug, 0))?;
+    class.define_singleton_method("debug=", magnus::function!(set_debug, 1))?;
+    class.define_singleton_method("stats", magnus::function!(stats, 0))?;
+    class.define_singleton_method("leaks", magnus::function!(leaks, 0))?;
+    class.define_singleton_method("__values", magnus::function!(values, 1))?;
+
     Ok(())
 }
 
+// locks the storage, first removing any ruby owned handles that were collected while it was locked before
+fn lock() -> MutexGuard<'static, HashMap<ExternStruct, Entry>> {
+    let mut storage = STORAGE.map.lock().unwrap();
+    let collected = std::mem::take(&mut *STORAGE.collected.lock().unwrap());
+    for (key, address) in collected {
+        remove_collected(&mut storage, key, address);
+    }
+    storage
+}
+
+fn remove_collected(storage: &mut HashMap<ExternStruct, Entry>, key: ExternStruct, address: usize) {
+    // the wrapper might have been replaced since (if the handle was released and FMOD reused it)
+    if storage.get(&key).and_then(|entry| entry.owned) == Some(address) {
+        let entry = storage.remove(&key).unwrap();
+        // FMOD could still be reading from the data until the handle is released
+        STORAGE.finalized.lock().unwrap().push((key, entry.data));
+    }
+}
+
+/// Called when a wrapper is garbage collected.
+///
+/// If the handle is owned by ruby it gets removed from the storage here, and released during the next `update`.
+/// FMOD can call back into ruby when releasing things, which isn't allowed during GC.
+pub fn finalize<T>(value: impl Into<ExternStruct>, wrapper: &T) {
+    let key = value.into();
+    let address = wrapper as *const T as usize;
+    // GC can happen while the storage is locked (it allocates), so this can't block
+    match STORAGE.map.try_lock() {
+        Ok(mut storage) => remove_collected(&mut storage, key, address),
+        Err(_) => STORAGE.collected.lock().unwrap().push((key, address)),
+    }
+}
+
+/// Marks a handle as owned by ruby (or not).
+///
+/// Ruby owned handles are no longer kept alive by the storage, and are released when their wrapper is collected.
+pub fn set_owned<T: magnus::TypedData>(
+    value: impl Into<ExternStruct>,
+    wrapper: Obj<T>,
+    owned: bool,
+) {
+    let mut storage = lock();
+    if let Some(entry) = storage.get_mut(&value.into()) {
+        entry.owned = owned.then(|| &*wrapper as *const T as usize);
+    }
+}
+
+/// Keeps `data` alive until the handle is removed from the storage.
+pub fn attach(value: impl Into<ExternStruct>, data: impl Any + Send + Sync) {
+    let mut storage = lock();
+    if let Some(entry) = storage.get_mut(&value.into()) {
+        entry.data.push(Box::new(data));
+    }
+}
+
+pub fn is_owned(value: impl Into<ExternStruct>) -> bool {
+    let storage = lock();
+    storage
+        .get(&value.into())
+        .is_some_and(|entry| entry.owned.is_some())
+}
+
+// releases every ruby owned handle that was collected. must not be called with the storage locked
+fn release_finalized() {
+    let finalized = std::mem::take(&mut *STORAGE.finalized.lock().unwrap());
+    for (key, data) in finalized {
+        key.release();
+        drop(data);
+    }
+}
+
+// Returns the stored wrapper for `key`, storing the one `f` creates if there isn't one.
+//
+// Anything that calls into FMOD (checking validity, asking who owns the handle) happens without the storage locked,
+// since FMOD can be waiting on a callback that needs the storage.
+// `replace_stale` replaces an entry whose handle is no longer valid, which means FMOD has reused a released handle.
+// That's only reliable for handles we set the default userdata on.
+fn get_or_insert_entry(
+    key: ExternStruct,
+    replace_stale: bool,
+    f: impl FnOnce() -> magnus::RTypedData,
+) -> magnus::RTypedData {
+    let backtrace = capture_backtrace();
+    let stale = replace_stale && !key.is_valid();
+
+    let (scope_owner, studio_systems) = {
+        let storage = lock();
+        match storage.get(&key) {
+            Some(entry) if !stale => return entry.value,
+            _ => (scope_owner(&storage), studio_systems(&storage)),
+        }
+    };
+    let owner = scope_owner
+        .or_else(|| key.query_owner(&studio_systems))
+        .filter(|&owner| owner != key);
+    if replace_stale {
+        key.set_default_userdata();
+    }
+
+    // creating the wrapper allocates, and a GC marks the storage, so this has to happen before locking it
+    let value = f();
+
+    let mut storage = lock();
+    match storage.get(&key).map(|entry| entry.value) {
+        // stored while we weren't looking, `value` is never handed out and gets collected
+        Some(existing) if !stale => return existing,
+        // the old entry's attachments were for the released handle, so they can go
+        Some(_) => drop(storage.remove(&key)),
+        None => {}
+    }
+    let stamp = STORAGE.next_stamp.fetch_add(1, Ordering::Relaxed);
+    if key.needs_sweep() {
+        STORAGE.sweep.lock().unwrap().push_back((key, stamp));
+    }
+    storage.insert(
+        key,
+        Entry {
+            value,
+            owner,
+            stamp,
+            backtrace,
+            owned: None,
+            data: Vec::new(),
+        },
+    );
+    value
+}
+
 pub(crate) fn get_or_insert<T, R>(value: T, ruby_val: R) -> Result<Obj<R>>
 where
     T: Into<ExternStruct>,
     R: magnus::TypedData,
 {
-    let mut storage = STORAGE.map.lock().unwrap();
-    let key = value.into();
-    key.set_default_userdata();
-    let &mut value = storage
-        .entry(key)
-        .or_insert_with(|| Obj::wrap(ruby_val).into());
+    let value = get_or_insert_entry(value.into(), true, || Obj::wrap(ruby_val).into());
+    Obj::try_convert(value.as_value())
+}
+
+/// Like [`get_or_insert`], but new wrappers are created as an instance of whatever `class` returns (a subclass of
+/// `R`'s class).
+pub(crate) fn get_or_insert_as<T, R, F>(value: T, ruby_val: R, class: F) -> Result<Obj<R>>
+where
+    T: Into<ExternStruct>,
+    R: magnus::TypedData,
+    F: FnOnce() -> magnus::RClass,
+{
+    let value = get_or_insert_entry(value.into(), true, || {
+        Obj::wrap_as(ruby_val, class()).into()
+    });
     Obj::try_convert(value.as_value())
 }
 
@@ -89,30 +361,267 @@ where
     R: magnus::TypedData,
     F: FnOnce() -> Obj<R>,
 {
-    let mut storage = STORAGE.map.lock().unwrap();
-    let key = value.into();
-    let &mut value = storage.entry(key).or_insert_with(|| f().into());
+    let value = get_or_insert_entry(value.into(), false, || f().into());
     Obj::try_convert(value.as_value())
 }
 
 pub fn remove(value: impl Into<ExternStruct>) {
-    let mut storage = STORAGE.map.lock().unwrap();
+    let mut storage = lock();
     let key = value.into();
     storage.remove(&key);
 }
 
+/// Removes a system along with everything that was created through it.
+///
+/// Studio systems own their core system, so releasing one removes the core system's children too.
+pub fn remove_with_children(system: impl Into<ExternStruct>) {
+    let mut storage = lock();
+    let mut owners = vec![system.into()];
+    while let Some(owner) = owners.pop() {
+        storage.remove(&owner);
+        storage.retain(|&key, entry| {
+            if entry.owner != Some(owner) {
+                return true;
+            }
+            if matches!(key, ExternStruct::System(_) | ExternStruct::StudioSystem(_)) {
+                owners.push(key);
+            }
+            false
+        });
+    }
+}
+
 pub fn contains(value: impl Into<ExternStruct>) -> bool {
-    let storage = STORAGE.map.lock().unwrap();
+    let storage = lock();
     let key = value.into();
     storage.contains_key(&key)
 }
 
+/// Identifies the wrapper stored for a handle. It changes if the handle is removed and stored again (like when FMOD
+/// reuses a released handle), so something that outlives a callback can tell whether it still has the same one.
+pub fn stamp(value: impl Into<ExternStruct>) -> Option<u64> {
+    let storage = lock();
+    storage.get(&value.into()).map(|entry| entry.stamp)
+}
+
+/// The studio system an event instance was created through.
+pub fn studio_system_of(event: EventInstance) -> Option<StudioSystem> {
+    let key = ExternStruct::EventInstance(event);
+    let (stored, studio_systems) = {
+        let storage = lock();
+        (storage.get(&key).map(|entry| entry.owner), studio_systems(&storage))
+    };
+    let owner = stored.unwrap_or_else(|| key.query_owner(&studio_systems));
+    match owner {
+        Some(ExternStruct::StudioSystem(system)) => Some(system),
+        _ => None,
+    }
+}
+
+/// Ruby objects for every stored handle matching `filter`.
+pub(crate) fn values_where(filter: impl Fn(&ExternStruct) -> bool) -> Vec<magnus::RTypedData> {
+    let storage = lock();
+    storage
+        .iter()
+        .filter(|(key, _)| filter(key))
+        .map(|(_, entry)| entry.value)
+        .collect()
+}
+
+/// Checks every stored handle, removing the ones that are no longer valid.
+///
+/// This is O(n) in the number of live handles, so it's only used when lots of handles are expected to have
+/// gone away at once (like when a system is released). `update` uses [`cleanup_step`] instead.
 pub fn cleanup() {
-    let mut storage = STORAGE.map.lock().unwrap();
+    release_finalized();
+    let mut storage = lock();
     storage.retain(|key, _| key.is_valid());
+
+    // rebuilding the queue also drops any keys that were removed since they were queued
+    let mut sweep = STORAGE.sweep.lock().unwrap();
+    sweep.clear();
+    sweep.extend(
+        storage
+            .iter()
+            .filter(|(key, _)| key.needs_sweep())
+            .map(|(&key, entry)| (key, entry.stamp)),
+    );
+}
+
+/// Checks at most `ExternStructStorage.cleanup_budget` handles, picking up where the last call left off.
+///
+/// Every handle is checked once every `len / budget` calls, so the cost per `update` stays the same no matter
+/// how many handles are alive.
+pub fn cleanup_step() {
+    release_finalized();
+    let mut storage = lock();
+    let mut sweep = STORAGE.sweep.lock().unwrap();
+
+    let budget = get_cleanup_budget().min(sweep.len());
+    for _ in 0..budget {
+        let Some((key, stamp)) = sweep.pop_front() else {
+            break;
+        };
+        // removed some other way since it was queued (and maybe inserted again under a new stamp)
+        if storage.get(&key).map(|entry| entry.stamp) != Some(stamp) {
+            continue;
+        }
+        if key.is_valid() {
+            sweep.push_back((key, stamp));
+        } else {
+            storage.remove(&key);
+        }
+    }
+}
+
+thread_local! {
+    // the handle whose method is running on this thread
+    static CURRENT_SCOPE: Cell<Option<ExternStruct>> = const { Cell::new(None) };
+}
+
+/// While this is alive, handles created on this thread are owned by the system that owns `handle`.
+///
+/// Every method call on a handle enters one of these, so anything it creates belongs to the same system.
+/// The owner is only looked up when something is actually created, so entering a scope never locks the storage.
+pub struct OwnerScope(Option<ExternStruct>);
+
+impl OwnerScope {
+    pub fn enter(handle: impl Into<ExternStruct>) -> Self {
+        Self(CURRENT_SCOPE.replace(Some(handle.into())))
+    }
+}
+
+impl Drop for OwnerScope {
+    fn drop(&mut self) {
+        CURRENT_SCOPE.set(self.0);
+    }
+}
+
+// the system that owns the handle whose method is running, which new handles belong to as well
+fn scope_owner(storage: &HashMap<ExternStruct, Entry>) -> Option<ExternStruct> {
+    CURRENT_SCOPE.get().and_then(|scope| match scope {
+        ExternStruct::System(_) | ExternStruct::StudioSystem(_) => Some(scope),
+        _ => storage.get(&scope).and_then(|entry| entry.owner),
+    })
+}
+
+fn studio_systems(storage: &HashMap<ExternStruct, Entry>) -> Vec<StudioSystem> {
+    storage
+        .keys()
+        .filter_map(|key| match key {
+            ExternStruct::StudioSystem(system) => Some(*system),
+            _ => None,
+        })
+        .collect()
 }
 
 impl ExternStruct {
+    // for handles created outside of a method call (in callbacks, mostly) ask FMOD where we can.
+    // this calls into FMOD, so the storage must not be locked
+    fn query_owner(&self, studio_systems: &[StudioSystem]) -> Option<ExternStruct> {
+        let system = match self {
+            ExternStruct::ChannelControl(c) => c.get_system(),
+            ExternStruct::Dsp(d) => d.get_system(),
+            ExternStruct::Sound(s) => s.get_system(),
+            ExternStruct::SoundGroup(s) => s.get_system(),
+            ExternStruct::DspConnection(c) => c.get_input().and_then(|dsp| dsp.get_system()),
+            ExternStruct::CommandReplay(c) => return c.get_system().ok().map(Into::into),
+            ExternStruct::Bank(_)
+            | ExternStruct::EventDescription(_)
+            | ExternStruct::EventInstance(_)
+            | ExternStruct::Bus(_)
+            | ExternStruct::Vca(_) => return self.query_studio_owner(studio_systems),
+            _ => return None,
+        };
+        system.ok().map(Into::into)
+    }
+
+    // studio handles can't tell us their system, so ask each studio system we know of whether it has them
+    fn query_studio_owner(&self, systems: &[StudioSystem]) -> Option<ExternStruct> {
+        if let [system] = systems[..] {
+            return Some(system.into());
+        }
+
+        let owns = |system: &StudioSystem| match *self {
+            ExternStruct::Bank(b) => b
+                .get_id()
+                .and_then(|id| system.get_bank_by_id(id))
+                .is_ok_and(|found| found == b),
+            ExternStruct::EventDescription(e) => e
+                .get_id()
+                .and_then(|id| system.get_event_by_id(id))
+                .is_ok_and(|found| found == e),
+            ExternStruct::EventInstance(e) => e.get_description().is_ok_and(|description| {
+                description
+                    .get_id()
+                    .and_then(|id| system.get_event_by_id(id))
+                    .is_ok_and(|found| found == description)
+            }),
+            ExternStruct::Bus(b) => b
+                .get_id()
+                .and_then(|id| system.get_bus_by_id(id))
+                .is_ok_and(|found| found == b),
+            ExternStruct::Vca(v) => v
+                .get_id()
+                .and_then(|id| system.get_vca_by_id(id))
+                .is_ok_and(|found| found == v),
+            _ => false,
+        };
+        systems.iter().copied().find(owns).map(Into::into)
+    }
+
+    // only for the types that can be owned by ruby
+    fn release(&self) {
+        let _ = match self {
+            ExternStruct::Sound(s) => {
+                let mut index = 0;
+                while let Ok(point) = s.get_sync_point(index) {
+                    remove(point);
+                    index += 1;
+                }
+                s.release()
+            }
+            ExternStruct::Dsp(d) => d.release(),
+            ExternStruct::ChannelControl(c) => {
+                let group: *mut fmod::ffi::FMOD_CHANNELCONTROL = (*c).into();
+                fmod::ChannelGroup::from(group.cast::<fmod::ffi::FMOD_CHANNELGROUP>()).release()
+            }
+            ExternStruct::SoundGroup(s) => s.release(),
+            ExternStruct::Geometry(g) => g.release(),
+            ExternStruct::Reverb3D(r) => r.release(),
+            ExternStruct::EventInstance(e) => e.release(),
+            ExternStruct::CommandReplay(c) => c.release(),
+            _ => Ok(()),
+        };
+    }
+
+    // the name used by `ExternStructStorage.stats` and friends
+    fn kind(&self) -> &'static str {
+        match self {
+            ExternStruct::StudioSystem(_) => "studio_system",
+            ExternStruct::Bank(_) => "bank",
+            ExternStruct::EventDescription(_) => "event_description",
+            ExternStruct::EventInstance(_) => "event_instance",
+            ExternStruct::CommandReplay(_) => "command_replay",
+            ExternStruct::Bus(_) => "bus",
+            ExternStruct::Vca(_) => "vca",
+            ExternStruct::Reverb3D(_) => "reverb_3d",
+            ExternStruct::SoundGroup(_) => "sound_group",
+            ExternStruct::ChannelControl(_) => "channel_control",
+            ExternStruct::Dsp(_) => "dsp",
+            ExternStruct::Sound(_) => "sound",
+            ExternStruct::Geometry(_) => "geometry",
+            ExternStruct::DspConnection(_) => "dsp_connection",
+            ExternStruct::SyncPoint(_) => "sync_point",
+            ExternStruct::System(_) => "system",
+        }
+    }
+
+    // these are always considered valid, so checking them would be a waste. they get removed explicitly
+    fn needs_sweep(&self) -> bool {
+        !matches!(self, ExternStruct::System(_) | ExternStruct::SyncPoint(_))
+    }
+
     fn set_default_userdata(&self) {
         let _ = match self {
             ExternStruct::Reverb3D(r) => r.set_raw_userdata(DEFAULT_USERDATA_PTR),
@@ -260,6 +769,18 @@ impl From<SyncPoint> for ExternStruct {
     }
 }
 
+impl From<fmod::Channel> for ExternStruct {
+    fn from(c: fmod::Channel) -> Self {
+        ExternStruct::ChannelControl(*c)
+    }
+}
+
+impl From<fmod::ChannelGroup> for ExternStruct {
+    fn from(c: fmod::ChannelGroup) -> Self {
+        ExternStruct::ChannelControl(*c)
+    }
+}
+
 impl From<System> for ExternStruct {
     fn from(s: System) -> Self {
         ExternStruct::System(s)
@@ -7,6 +7,7 @@ mod callback;
 mod core;
 mod error;
 mod extern_struct_storage;
+mod listeners;
 mod studio;
 mod thread;
 
@@ -26,7 +27,8 @@ fn init(ruby: &magnus::Ruby) -> Result<()> {
     core::bind(module)?;
     studio::bind(module)?;
     extern_struct_storage::bind(module)?;
-    callback::bind();
+    listeners::bind(module)?;
+    callback::bind(module)?;
 
     Ok(())
 }
@@ -0,0 +1,235 @@
+// Copyright (c) 2024 Lily Lyons
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use magnus::{
+    prelude::*,
+    typed_data::Obj,
+    value::{InnerValue, Opaque},
+    TryConvert,
+};
+use std::cell::{Cell, RefCell};
+
+use crate::Result;
+
+/// The callbacks a type supports, along with the mask bit that enables each one.
+pub type Events = &'static [(&'static str, u32)];
+/// Applies a callback mask to the owner, setting its FMOD callback.
+pub type ApplyMask = fn(magnus::Value, u32) -> Result<()>;
+
+struct Listener {
+    id: u64,
+    event: &'static str,
+    block: Opaque<magnus::block::Proc>,
+}
+
+/// Block based callbacks, stored in place of a callback object in `__callback`.
+///
+/// A callback object that was set before the first listener was added is kept around and still called.
+#[derive(magnus::TypedData)]
+#[magnus(class = "FMOD::Listeners", mark)]
+pub struct Listeners {
+    owner: Opaque<magnus::Value>,
+    events: Events,
+    apply: ApplyMask,
+    fallback: Option<(Opaque<magnus::Value>, u32)>,
+    listeners: RefCell<Vec<Listener>>,
+    next_id: Cell<u64>,
+}
+
+impl magnus::DataTypeFunctions for Listeners {
+    fn mark(&self, marker: &magnus::gc::Marker) {
+        let ruby = magnus::Ruby::get().unwrap();
+        marker.mark(self.owner.get_inner_with(&ruby));
+        if let Some((fallback, _)) = self.fallback {
+            marker.mark(fallback.get_inner_with(&ruby));
+        }
+        for listener in self.listeners.borrow().iter() {
+            marker.mark(listener.block.get_inner_with(&ruby));
+        }
+    }
+}
+
+/// Handle returned by `on`, used to remove the listener again.
+#[derive(magnus::TypedData)]
+#[magnus(class = "FMOD::Subscription", mark)]
+struct Subscription {
+    listeners: Opaque<Obj<Listeners>>,
+    id: u64,
+    event: &'static str,
+}
+
+impl magnus::DataTypeFunctions for Subscription {
+    fn mark(&self, marker: &magnus::gc::Marker) {
+        let ruby = magnus::Ruby::get().unwrap();
+        marker.mark(self.listeners.get_inner_with(&ruby));
+    }
+}
+
+impl Listeners {
+    fn mask(&self) -> u32 {
+        let listeners = self.listeners.borrow();
+        let fallback_mask = self.fallback.map_or(0, |(_, mask)| mask);
+        self.events
+            .iter()
+            .filter(|(name, _)| listeners.iter().any(|l| l.event == *name))
+            .fold(fallback_mask, |mask, (_, bit)| mask | bit)
+    }
+
+    fn apply(&self) -> Result<()> {
+        let ruby = magnus::Ruby::get().unwrap();
+        (self.apply)(self.owner.get_inner_with(&ruby), self.mask())
+    }
+}
+
+/// Registers `block` to be called whenever `event` fires on `owner`.
+///
+/// `events` and `apply` describe the owner; `apply` is called again with the new mask every time the set of
+/// events with listeners changes.
+pub fn subscribe(
+    owner: impl magnus::Object,
+    event: magnus::Symbol,
+    block: magnus::block::Proc,
+    events: Events,
+    apply: ApplyMask,
+) -> Result<magnus::Value> {
+    let ruby = magnus::Ruby::get().unwrap();
+    let event_name = event.name()?;
+    let Some(&(event, _)) = events.iter().find(|(name, _)| *name == event_name) else {
+        let names: Vec<_> = events.iter().map(|(name, _)| format!(":{name}")).collect();
+        return Err(magnus::Error::new(
+            magnus::exception::arg_error(),
+            format!(
+                "unknown event :{event_name} (expected one of {})",
+                names.join(", ")
+            ),
+        ));
+    };
+
+    let current: magnus::Value = owner.ivar_get("__callback")?;
+    let listeners = match Obj::<Listeners>::try_convert(current) {
+        Ok(listeners) => listeners,
+        Err(_) => {
+            // keep any callback object that was already set, using whatever mask it was set with
+            let fallback = if current.is_nil() {
+                None
+            } else {
+                let mask: Option<u32> = owner.ivar_get("__callback_mask")?;
+                Some((current.into(), mask.unwrap_or(u32::MAX)))
+            };
+            let listeners = ruby.obj_wrap(Listeners {
+                owner: owner.as_value().into(),
+                events,
+                apply,
+                fallback,
+                listeners: RefCell::default(),
+                next_id: Cell::new(0),
+            });
+            owner.ivar_set("__callback", listeners)?;
+            listeners
+        }
+    };
+
+    let id = listeners.next_id.get();
+    listeners.next_id.set(id + 1);
+    listeners.listeners.borrow_mut().push(Listener {
+        id,
+        event,
+        block: block.into(),
+    });
+    listeners.apply()?;
+
+    let subscription = Subscription {
+        listeners: listeners.into(),
+        id,
+        event,
+    };
+    Ok(ruby.obj_wrap(subscription).as_value())
+}
+
+/// Calls the callback `name` on `callback`, which is either a callback object or a [`Listeners`].
+///
+/// When several listeners are registered for a callback that returns something, the first non-nil result wins.
+pub fn dispatch<A, T>(callback: magnus::Value, name: &'static str, args: A) -> Result<T>
+where
+    A: magnus::ArgList + Copy,
+    T: magnus::TryConvert,
+{
+    let Ok(listeners) = Obj::<Listeners>::try_convert(callback) else {
+        return callback.funcall(name, args);
+    };
+    let ruby = magnus::Ruby::get().unwrap();
+
+    let mut result = ruby.qnil().as_value();
+    if let Some((fallback, mask)) = listeners.fallback {
+        let enabled = listeners
+            .events
+            .iter()
+            .any(|&(event, bit)| event == name && mask & bit != 0);
+        if enabled {
+            result = fallback.get_inner_with(&ruby).funcall(name, args)?;
+        }
+    }
+
+    // collect the blocks first so listeners can subscribe/unsubscribe from inside a callback
+    let blocks: Vec<_> = listeners
+        .listeners
+        .borrow()
+        .iter()
+        .filter(|listener| listener.event == name)
+        .map(|listener| listener.block)
+        .collect();
+    for block in blocks {
+        let value: magnus::Value = block.get_inner_with(&ruby).funcall("call", args)?;
+        if result.is_nil() {
+            result = value;
+        }
+    }
+
+    T::try_convert(result)
+}
+
+impl Subscription {
+    fn unsubscribe(&self) -> Result<bool> {
+        let ruby = magnus::Ruby::get().unwrap();
+        let listeners = self.listeners.get_inner_with(&ruby);
+        let removed = {
+            let mut list = listeners.listeners.borrow_mut();
+            let len = list.len();
+            list.retain(|listener| listener.id != self.id);
+            list.len() != len
+        };
+        if removed {
+            listeners.apply()?;
+        }
+        Ok(removed)
+    }
+
+    fn is_subscribed(&self) -> bool {
+        let ruby = magnus::Ruby::get().unwrap();
+        let listeners = self.listeners.get_inner_with(&ruby);
+        let list = listeners.listeners.borrow();
+        list.iter().any(|listener| listener.id == self.id)
+    }
+
+    fn event(&self) -> magnus::Symbol {
+        let ruby = magnus::Ruby::get().unwrap();
+        ruby.to_symbol(self.event)
+    }
+}
+
+pub fn bind(module: magnus::RModule) -> Result<()> {
+    module.define_class("Listeners", magnus::class::object())?;
+
+    let class = module.define_class("Subscription", magnus::class::object())?;
+    class.define_method("unsubscribe", magnus::method!(Subscription::unsubscribe, 0))?;
+    class.define_method(
+        "subscribed?",
+        magnus::method!(Subscription::is_subscribed, 0),
+    )?;
+    class.define_method("event", magnus::method!(Subscription::event, 0))?;
+
+    Ok(())
+}
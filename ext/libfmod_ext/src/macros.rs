@@ -11,11 +11,18 @@ use crate::Result;
 
 #[macro_export]
 macro_rules! extern_struct {
-    (struct $name:ident: $fmod_ty:path => $ruby_path:literal) => {
-        #[magnus::wrap(class = $ruby_path, free_immediately, size)]
-        #[derive(Clone, Copy, PartialEq, Eq, Hash)]
+    // `class` picks the class new wrappers are created with, which must be a subclass of `$ruby_path`
+    (struct $name:ident: $fmod_ty:path => $ruby_path:literal $(, class = $class_fn:path)?) => {
+        #[derive(Clone, Copy, PartialEq, Eq, Hash, magnus::TypedData)]
+        #[magnus(class = $ruby_path, free_immediately, size)]
         pub struct $name(pub $fmod_ty);
 
+        impl magnus::DataTypeFunctions for $name {
+            fn free(self: Box<Self>) {
+                $crate::extern_struct_storage::finalize(self.0, &*self);
+            }
+        }
+
         impl std::fmt::Debug for $name {
             fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
                 self.0.fmt(f)
@@ -34,8 +41,12 @@ macro_rules! extern_struct {
           }
 
           impl $crate::IntoRuby<[<Rb $name>]> for $fmod_ty {
+              #[allow(unreachable_code)]
               fn into_ruby(self) -> $crate::Result<[<Rb $name>]> {
                   let rb_self = $name(self);
+                  $(
+                    return $crate::extern_struct_storage::get_or_insert_as(self, rb_self, || $class_fn(self));
+                  )?
                   $crate::extern_struct_storage::get_or_insert(self, rb_self)
               }
           }
@@ -55,6 +66,8 @@ macro_rules! extern_struct_fns {
               #[allow(unused_imports)]
               use $crate::{FromRuby, IntoRuby};
               let this: $fmod_ty = rb_self.from_ruby()?;
+              // anything created by this call belongs to the same system as `this`
+              let _scope = $crate::extern_struct_storage::OwnerScope::enter(this);
               $(
                 let $arg_name = $arg_name.from_ruby()?;
               )*
@@ -538,7 +551,7 @@ where
 
 impl IntoRuby<magnus::Exception> for fmod::Error {
     fn into_ruby(self) -> Result<magnus::Exception> {
-        crate::error::class().new_instance((self.to_string(),))
+        crate::error::exception_from_fmod(self)
     }
 }
 
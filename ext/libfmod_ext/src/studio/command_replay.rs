@@ -21,7 +21,6 @@ extern_struct! {
 
 extern_struct_fns! {
     impl CommandReplay: fmod::studio::CommandReplay {
-        fn release() -> ();
         fn start() -> ();
         fn stop() -> ();
         fn get_current_command() -> (i32, f32);
@@ -42,6 +41,23 @@ extern_struct_fns! {
 }
 
 impl CommandReplay {
+    fn release(rb_self: RbCommandReplay) -> Result<()> {
+        let replay: fmod::studio::CommandReplay = rb_self.from_ruby()?;
+        crate::extern_struct_storage::remove(replay);
+        replay.release().into_ruby()
+    }
+
+    fn set_owned_by_ruby(rb_self: RbCommandReplay, owned: bool) -> Result<()> {
+        let replay: fmod::studio::CommandReplay = rb_self.from_ruby()?;
+        crate::extern_struct_storage::set_owned(replay, rb_self, owned);
+        Ok(())
+    }
+
+    fn is_owned_by_ruby(rb_self: RbCommandReplay) -> Result<bool> {
+        let replay: fmod::studio::CommandReplay = rb_self.from_ruby()?;
+        Ok(crate::extern_struct_storage::is_owned(replay))
+    }
+
     fn get_userdata(rb_self: RbCommandReplay) -> Result<magnus::Value> {
         rb_self.ivar_get("__userdata")
     }
@@ -117,6 +133,8 @@ extern_struct_bind! {
         fn get_userdata -> 0;
         fn set_userdata -> 1;
         fn release -> 0;
+        fn set_owned_by_ruby -> 1;
+        fn is_owned_by_ruby -> 0;
         fn start -> 0;
         fn stop -> 0;
         fn get_current_command -> 0;
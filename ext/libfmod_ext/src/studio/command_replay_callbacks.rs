@@ -28,25 +28,19 @@ impl fmod::studio::CreateInstanceCallback for CreateInstanceCallback {
         description: fmod::studio::EventDescription,
         _: *mut std::ffi::c_void,
     ) -> fmod::Result<Option<fmod::studio::EventInstance>> {
-        let (sender, reciever) = oneshot::channel();
-        callback::process(move |_| {
-            let replay: RbCommandReplay = replay.into_ruby().unwrap();
-            let description: RbEventDescription = description.into_ruby().unwrap();
-            let userdata: magnus::Value = replay.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = replay.ivar_get("__create_instance_callback").unwrap();
-
-            let result: Option<RbEventInstance> = callback
-                .funcall(
-                    "create_programmer_sound",
-                    (replay, command_index, description, userdata),
-                )
-                .unwrap();
-            let result = result.map(|event_instance| event_instance.from_ruby().unwrap());
-
-            sender.send(result).unwrap();
-        });
-        let result = reciever.recv().unwrap();
-        Ok(result)
+        callback::call("create_instance_callback", &replay, move |_| {
+            let replay: RbCommandReplay = replay.into_ruby()?;
+            let description: RbEventDescription = description.into_ruby()?;
+            let userdata: magnus::Value = replay.ivar_get("__userdata")?;
+            let callback: magnus::RObject = replay.ivar_get("__create_instance_callback")?;
+
+            let result: Option<RbEventInstance> = callback.funcall(
+                "create_instance_callback",
+                (replay, command_index, description, userdata),
+            )?;
+            let result: Option<fmod::studio::EventInstance> = result.from_ruby()?;
+            Ok(result)
+        })
     }
 }
 
@@ -64,23 +58,17 @@ impl fmod::studio::FrameCallback for FrameCallback {
         current_time: std::ffi::c_float,
         _: *mut std::ffi::c_void,
     ) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel();
-        callback::process(move |_| {
-            let replay: RbCommandReplay = replay.into_ruby().unwrap();
-            let userdata: magnus::Value = replay.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = replay.ivar_get("__frame_callback").unwrap();
-
-            let _: magnus::Value = callback
-                .funcall(
-                    "frame_callback",
-                    (replay, command_index, current_time, userdata),
-                )
-                .unwrap();
-
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::call("frame_callback", &replay, move |_| {
+            let replay: RbCommandReplay = replay.into_ruby()?;
+            let userdata: magnus::Value = replay.ivar_get("__userdata")?;
+            let callback: magnus::RObject = replay.ivar_get("__frame_callback")?;
+
+            let _: magnus::Value = callback.funcall(
+                "frame_callback",
+                (replay, command_index, current_time, userdata),
+            )?;
+            Ok(())
+        })
     }
 }
 
@@ -100,28 +88,22 @@ impl fmod::studio::LoadBankCallback for LoadBankCallback {
         flags: fmod::studio::LoadBankFlags,
         _: *mut std::ffi::c_void,
     ) -> fmod::Result<Option<fmod::studio::Bank>> {
-        let (sender, reciever) = oneshot::channel();
         let filename = filename.map(fmod::Utf8CStr::to_cstring);
-        callback::process(move |_| {
-            let replay: RbCommandReplay = replay.into_ruby().unwrap();
-            let guid = guid.map(|guid| guid.into_ruby().unwrap());
-            let filename = filename.map(|filename| filename.into_ruby().unwrap());
-            let flags = flags.into_ruby().unwrap();
-            let userdata: magnus::Value = replay.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = replay.ivar_get("__load_bank_callback").unwrap();
-
-            let result: Option<RbBank> = callback
-                .funcall(
-                    "load_bank_callback",
-                    (replay, command_index, guid, filename, flags, userdata),
-                )
-                .unwrap();
-            let result = result.map(|bank| bank.from_ruby().unwrap());
-
-            sender.send(result).unwrap();
-        });
-        let result = reciever.recv().unwrap();
-        Ok(result)
+        callback::call("load_bank_callback", &replay, move |_| {
+            let replay: RbCommandReplay = replay.into_ruby()?;
+            let guid: Option<Guid> = guid.into_ruby()?;
+            let filename: Option<magnus::RString> = filename.into_ruby()?;
+            let flags: LoadBankFlags = flags.into_ruby()?;
+            let userdata: magnus::Value = replay.ivar_get("__userdata")?;
+            let callback: magnus::RObject = replay.ivar_get("__load_bank_callback")?;
+
+            let result: Option<RbBank> = callback.funcall(
+                "load_bank_callback",
+                (replay, command_index, guid, filename, flags, userdata),
+            )?;
+            let result: Option<fmod::studio::Bank> = result.from_ruby()?;
+            Ok(result)
+        })
     }
 }
 
@@ -3,7 +3,9 @@
 // This Source Code Form is subject to the terms of the Mozilla Public
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
-use crate::{callback, core::sound::RbSound, FromRuby, IntoRuby, Result};
+use crate::{
+    callback, core::sound::RbSound, extern_struct_storage, listeners, FromRuby, IntoRuby, Result,
+};
 use magnus::{
     prelude::*,
     value::{InnerValue, Opaque},
@@ -14,112 +16,156 @@ use super::{event_description::RbEventDescription, event_instance::RbEventInstan
 
 static CLASS: OnceCell<Opaque<magnus::RClass>> = OnceCell::new();
 
+/// Events that can be listened to with `EventInstance#on` and `EventDescription#on`.
+pub const EVENTS: listeners::Events = &[
+    ("created", fmod::ffi::FMOD_STUDIO_EVENT_CALLBACK_CREATED),
+    ("destroyed", fmod::ffi::FMOD_STUDIO_EVENT_CALLBACK_DESTROYED),
+    ("starting", fmod::ffi::FMOD_STUDIO_EVENT_CALLBACK_STARTING),
+    ("started", fmod::ffi::FMOD_STUDIO_EVENT_CALLBACK_STARTED),
+    ("restarted", fmod::ffi::FMOD_STUDIO_EVENT_CALLBACK_RESTARTED),
+    ("stopped", fmod::ffi::FMOD_STUDIO_EVENT_CALLBACK_STOPPED),
+    (
+        "start_failed",
+        fmod::ffi::FMOD_STUDIO_EVENT_CALLBACK_START_FAILED,
+    ),
+    (
+        "create_programmer_sound",
+        fmod::ffi::FMOD_STUDIO_EVENT_CALLBACK_CREATE_PROGRAMMER_SOUND,
+    ),
+    (
+        "destroy_programmer_sound",
+        fmod::ffi::FMOD_STUDIO_EVENT_CALLBACK_DESTROY_PROGRAMMER_SOUND,
+    ),
+    (
+        "plugin_created",
+        fmod::ffi::FMOD_STUDIO_EVENT_CALLBACK_PLUGIN_CREATED,
+    ),
+    (
+        "plugin_destroyed",
+        fmod::ffi::FMOD_STUDIO_EVENT_CALLBACK_PLUGIN_DESTROYED,
+    ),
+    (
+        "timeline_marker",
+        fmod::ffi::FMOD_STUDIO_EVENT_CALLBACK_TIMELINE_MARKER,
+    ),
+    (
+        "timeline_beat",
+        fmod::ffi::FMOD_STUDIO_EVENT_CALLBACK_TIMELINE_BEAT,
+    ),
+    (
+        "sound_played",
+        fmod::ffi::FMOD_STUDIO_EVENT_CALLBACK_SOUND_PLAYED,
+    ),
+    (
+        "sound_stopped",
+        fmod::ffi::FMOD_STUDIO_EVENT_CALLBACK_SOUND_STOPPED,
+    ),
+    (
+        "real_to_virtual",
+        fmod::ffi::FMOD_STUDIO_EVENT_CALLBACK_REAL_TO_VIRTUAL,
+    ),
+    (
+        "virtual_to_real",
+        fmod::ffi::FMOD_STUDIO_EVENT_CALLBACK_VIRTUAL_TO_REAL,
+    ),
+    (
+        "start_event_command",
+        fmod::ffi::FMOD_STUDIO_EVENT_CALLBACK_START_EVENT_COMMAND,
+    ),
+    (
+        "nested_timeline_beat",
+        fmod::ffi::FMOD_STUDIO_EVENT_CALLBACK_NESTED_TIMELINE_BEAT,
+    ),
+];
+
 pub struct EventInstanceCallback;
 
 impl fmod::studio::EventInstanceCallback for EventInstanceCallback {
     fn created(event_instance: fmod::studio::EventInstance) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let event_instance = event_instance.into_ruby().unwrap();
-            let callback = EventInstanceCallback::get_callback(event_instance);
-            let _: magnus::Value = callback.funcall("created", (event_instance,)).unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        defer_event("created", event_instance, move |event_instance| {
+            let callback = EventInstanceCallback::get_callback(event_instance)?;
+            let _: magnus::Value = listeners::dispatch(callback, "created", (event_instance,))?;
+            Ok(())
+        })
     }
 
     fn destroyed(event: fmod::studio::EventInstance) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let event_instance = event.into_ruby().unwrap();
-            let callback = EventInstanceCallback::get_callback(event_instance);
-            let _: magnus::Value = callback.funcall("destroyed", (event_instance,)).unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        // deferred like the rest, so it can't overtake `stopped`. the instance is gone by the time the queue is
+        // drained, so its description has to be looked up now
+        let description = event.get_description().ok();
+        let stamp = extern_struct_storage::stamp(event);
+        // an instance without a wrapper has nothing to hand out once it's gone, so that's delivered straight away
+        let queue = stamp.and(studio_queue(event));
+        defer_with_stamp(queue, stamp, "destroyed", event, move |event_instance| {
+            let mut callback: magnus::Value = event_instance.ivar_get("__callback")?;
+            if let (true, Some(description)) = (callback.is_nil(), description) {
+                let description: RbEventDescription = description.into_ruby()?;
+                callback = description.ivar_get("__callback")?;
+            }
+            let _: magnus::Value = listeners::dispatch(callback, "destroyed", (event_instance,))?;
+            Ok(())
+        })
     }
 
     fn starting(event: fmod::studio::EventInstance) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let event_instance = event.into_ruby().unwrap();
-            let callback = EventInstanceCallback::get_callback(event_instance);
-            let _: magnus::Value = callback.funcall("starting", (event_instance,)).unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        defer_event("starting", event, move |event_instance| {
+            let callback = EventInstanceCallback::get_callback(event_instance)?;
+            let _: magnus::Value = listeners::dispatch(callback, "starting", (event_instance,))?;
+            Ok(())
+        })
     }
 
     fn started(event: fmod::studio::EventInstance) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let event_instance = event.into_ruby().unwrap();
-            let callback = EventInstanceCallback::get_callback(event_instance);
-            let _: magnus::Value = callback.funcall("started", (event_instance,)).unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        defer_event("started", event, move |event_instance| {
+            let callback = EventInstanceCallback::get_callback(event_instance)?;
+            let _: magnus::Value = listeners::dispatch(callback, "started", (event_instance,))?;
+            Ok(())
+        })
     }
 
     fn restarted(event: fmod::studio::EventInstance) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let event_instance = event.into_ruby().unwrap();
-            let callback = EventInstanceCallback::get_callback(event_instance);
-            let _: magnus::Value = callback.funcall("restarted", (event_instance,)).unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        defer_event("restarted", event, move |event_instance| {
+            let callback = EventInstanceCallback::get_callback(event_instance)?;
+            let _: magnus::Value = listeners::dispatch(callback, "restarted", (event_instance,))?;
+            Ok(())
+        })
     }
 
     fn stopped(event: fmod::studio::EventInstance) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let event_instance = event.into_ruby().unwrap();
-            let callback = EventInstanceCallback::get_callback(event_instance);
-            let _: magnus::Value = callback.funcall("stopped", (event_instance,)).unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        defer_event("stopped", event, move |event_instance| {
+            let callback = EventInstanceCallback::get_callback(event_instance)?;
+            let _: magnus::Value = listeners::dispatch(callback, "stopped", (event_instance,))?;
+            Ok(())
+        })
     }
 
     fn start_failed(event: fmod::studio::EventInstance) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let event_instance = event.into_ruby().unwrap();
-            let callback = EventInstanceCallback::get_callback(event_instance);
-            let _: magnus::Value = callback.funcall("start_failed", (event_instance,)).unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        defer_event("start_failed", event, move |event_instance| {
+            let callback = EventInstanceCallback::get_callback(event_instance)?;
+            let _: magnus::Value =
+                listeners::dispatch(callback, "start_failed", (event_instance,))?;
+            Ok(())
+        })
     }
 
     fn create_programmer_sound(
         event: fmod::studio::EventInstance,
         sound_props: fmod::studio::ProgrammerSoundProperties<'_>,
     ) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<Option<(fmod::Sound, i32)>>();
-        callback::process(move |_| {
-            let event_instance = event.into_ruby().unwrap();
-            let callback = EventInstanceCallback::get_callback(event_instance);
-            let name = sound_props.name.into_ruby().unwrap();
-
-            let result: Option<(RbSound, i32)> = callback
-                .funcall("create_programmer_sound", (event_instance, name))
-                .unwrap();
-            let result =
-                result.map(|(sound, subsound_index)| (sound.from_ruby().unwrap(), subsound_index));
-
-            sender.send(result).unwrap();
-        });
-        let result = reciever.recv().unwrap();
+        let name = sound_props.name;
+        let result = callback::call("create_programmer_sound", &event, move |_| {
+            let event_instance = event.into_ruby()?;
+            let callback = EventInstanceCallback::get_callback(event_instance)?;
+            let name = name.into_ruby()?;
+
+            let result: Option<(RbSound, i32)> =
+                listeners::dispatch(callback, "create_programmer_sound", (event_instance, name))?;
+            let result: Option<(fmod::Sound, i32)> = match result {
+                Some((sound, subsound_index)) => Some((sound.from_ruby()?, subsound_index)),
+                None => None,
+            };
+            Ok(result)
+        })?;
         if let Some((sound, subsound_index)) = result {
             *sound_props.sound = sound;
             *sound_props.subsound_index = subsound_index;
@@ -131,206 +177,210 @@ impl fmod::studio::EventInstanceCallback for EventInstanceCallback {
         event: fmod::studio::EventInstance,
         sound_props: fmod::studio::ProgrammerSoundProperties<'_>,
     ) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
         let fmod::studio::ProgrammerSoundProperties {
             sound: &mut sound,
             name,
             subsound_index: &mut subsound_index,
         } = sound_props;
 
-        callback::process(move |_| {
-            let event_instance = event.into_ruby().unwrap();
-            let callback = EventInstanceCallback::get_callback(event_instance);
-            let name = name.into_ruby().unwrap();
-            let sound = sound.into_ruby().unwrap();
-            let _: magnus::Value = callback
-                .funcall(
-                    "destroy_programmer_sound",
-                    (event_instance, name, sound, subsound_index),
-                )
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-
-        Ok(())
+        callback::call("destroy_programmer_sound", &event, move |_| {
+            let event_instance = event.into_ruby()?;
+            let callback = EventInstanceCallback::get_callback(event_instance)?;
+            let name = name.into_ruby()?;
+            let sound = sound.into_ruby()?;
+            let _: magnus::Value = listeners::dispatch(
+                callback,
+                "destroy_programmer_sound",
+                (event_instance, name, sound, subsound_index),
+            )?;
+            Ok(())
+        })
     }
 
     fn plugin_created(
         event: fmod::studio::EventInstance,
         plugin_props: fmod::studio::PluginInstanceProperties,
     ) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let event_instance = event.into_ruby().unwrap();
-            let callback = EventInstanceCallback::get_callback(event_instance);
-            let plugin_props = plugin_props.into_ruby().unwrap();
-            let _: magnus::Value = callback
-                .funcall("plugin_created", (event_instance, plugin_props))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::call("plugin_created", &event, move |_| {
+            let event_instance = event.into_ruby()?;
+            let callback = EventInstanceCallback::get_callback(event_instance)?;
+            let plugin_props = plugin_props.into_ruby()?;
+            let _: magnus::Value =
+                listeners::dispatch(callback, "plugin_created", (event_instance, plugin_props))?;
+            Ok(())
+        })
     }
 
     fn plugin_destroyed(
         event: fmod::studio::EventInstance,
         plugin_props: fmod::studio::PluginInstanceProperties,
     ) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let event_instance = event.into_ruby().unwrap();
-            let callback = EventInstanceCallback::get_callback(event_instance);
-            let plugin_props = plugin_props.into_ruby().unwrap();
-            let _: magnus::Value = callback
-                .funcall("plugin_destroyed", (event_instance, plugin_props))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::call("plugin_destroyed", &event, move |_| {
+            let event_instance = event.into_ruby()?;
+            let callback = EventInstanceCallback::get_callback(event_instance)?;
+            let plugin_props = plugin_props.into_ruby()?;
+            let _: magnus::Value =
+                listeners::dispatch(callback, "plugin_destroyed", (event_instance, plugin_props))?;
+            Ok(())
+        })
     }
 
     fn timeline_marker(
         event: fmod::studio::EventInstance,
         timeline_props: fmod::studio::TimelineMarkerProperties,
     ) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let event_instance = event.into_ruby().unwrap();
-            let callback = EventInstanceCallback::get_callback(event_instance);
-            let timeline_props = timeline_props.into_ruby().unwrap();
-            let _: magnus::Value = callback
-                .funcall("timeline_marker", (event_instance, timeline_props))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        defer_event("timeline_marker", event, move |event_instance| {
+            let callback = EventInstanceCallback::get_callback(event_instance)?;
+            let timeline_props = timeline_props.into_ruby()?;
+            let _: magnus::Value = listeners::dispatch(
+                callback,
+                "timeline_marker",
+                (event_instance, timeline_props),
+            )?;
+            Ok(())
+        })
     }
 
     fn timeline_beat(
         event: fmod::studio::EventInstance,
         timeline_beat: fmod::studio::TimelineBeatProperties,
     ) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let event_instance = event.into_ruby().unwrap();
-            let callback = EventInstanceCallback::get_callback(event_instance);
-            let timeline_beat = timeline_beat.into_ruby().unwrap();
-            let _: magnus::Value = callback
-                .funcall("timeline_beat", (event_instance, timeline_beat))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        defer_event("timeline_beat", event, move |event_instance| {
+            let callback = EventInstanceCallback::get_callback(event_instance)?;
+            let timeline_beat = timeline_beat.into_ruby()?;
+            let _: magnus::Value = listeners::dispatch(
+                callback,
+                "timeline_beat",
+                (event_instance, timeline_beat),
+            )?;
+            Ok(())
+        })
     }
 
     fn sound_played(event: fmod::studio::EventInstance, sound: fmod::Sound) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let event_instance = event.into_ruby().unwrap();
-            let sound = sound.into_ruby().unwrap();
-            let callback = EventInstanceCallback::get_callback(event_instance);
-            let _: magnus::Value = callback
-                .funcall("sound_played", (event_instance, sound))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        // never deferred, studio may have freed the sound by the time the queue is drained
+        callback::call("sound_played", &event, move |_| {
+            let event_instance = event.into_ruby()?;
+            let sound = sound.into_ruby()?;
+            let callback = EventInstanceCallback::get_callback(event_instance)?;
+            let _: magnus::Value =
+                listeners::dispatch(callback, "sound_played", (event_instance, sound))?;
+            Ok(())
+        })
     }
 
     fn sound_stopped(event: fmod::studio::EventInstance, sound: fmod::Sound) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let event_instance = event.into_ruby().unwrap();
-            let sound = sound.into_ruby().unwrap();
-            let callback = EventInstanceCallback::get_callback(event_instance);
-            let _: magnus::Value = callback
-                .funcall("sound_stopped", (event_instance, sound))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::call("sound_stopped", &event, move |_| {
+            let event_instance = event.into_ruby()?;
+            let sound = sound.into_ruby()?;
+            let callback = EventInstanceCallback::get_callback(event_instance)?;
+            let _: magnus::Value =
+                listeners::dispatch(callback, "sound_stopped", (event_instance, sound))?;
+            Ok(())
+        })
     }
 
     fn real_to_virtual(event: fmod::studio::EventInstance) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let event_instance = event.into_ruby().unwrap();
-            let callback = EventInstanceCallback::get_callback(event_instance);
-            let _: magnus::Value = callback
-                .funcall("real_to_virtual", (event_instance,))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        defer_event("real_to_virtual", event, move |event_instance| {
+            let callback = EventInstanceCallback::get_callback(event_instance)?;
+            let _: magnus::Value =
+                listeners::dispatch(callback, "real_to_virtual", (event_instance,))?;
+            Ok(())
+        })
     }
 
     fn virtual_to_real(event: fmod::studio::EventInstance) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let event_instance = event.into_ruby().unwrap();
-            let callback = EventInstanceCallback::get_callback(event_instance);
-            let _: magnus::Value = callback
-                .funcall("virtual_to_real", (event_instance,))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        defer_event("virtual_to_real", event, move |event_instance| {
+            let callback = EventInstanceCallback::get_callback(event_instance)?;
+            let _: magnus::Value =
+                listeners::dispatch(callback, "virtual_to_real", (event_instance,))?;
+            Ok(())
+        })
     }
 
     fn start_event_command(
         event: fmod::studio::EventInstance,
         new_event: fmod::studio::EventInstance,
     ) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let event_instance = event.into_ruby().unwrap();
-            let new_event_instance = new_event.into_ruby().unwrap();
-            let callback = EventInstanceCallback::get_callback(event_instance);
-            let _: magnus::Value = callback
-                .funcall("start_event_command", (event_instance, new_event_instance))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        defer_event("start_event_command", event, move |event_instance| {
+            let new_event_instance = new_event.into_ruby()?;
+            let callback = EventInstanceCallback::get_callback(event_instance)?;
+            let _: magnus::Value = listeners::dispatch(
+                callback,
+                "start_event_command",
+                (event_instance, new_event_instance),
+            )?;
+            Ok(())
+        })
     }
 
     fn nested_timeline_beat(
         event: fmod::studio::EventInstance,
         timeline_props: fmod::studio::TimelineNestedBeatProperties,
     ) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let event_instance = event.into_ruby().unwrap();
-            let callback = EventInstanceCallback::get_callback(event_instance);
-            let timeline_props = timeline_props.into_ruby().unwrap();
-            let _: magnus::Value = callback
-                .funcall("nested_timeline_beat", (event_instance, timeline_props))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        defer_event("nested_timeline_beat", event, move |event_instance| {
+            let callback = EventInstanceCallback::get_callback(event_instance)?;
+            let timeline_props = timeline_props.into_ruby()?;
+            let _: magnus::Value = listeners::dispatch(
+                callback,
+                "nested_timeline_beat",
+                (event_instance, timeline_props),
+            )?;
+            Ok(())
+        })
     }
 }
 
+// event callbacks are queued on the studio system the instance was created through
+fn studio_queue(event: fmod::studio::EventInstance) -> Option<callback::Queue> {
+    extern_struct_storage::studio_system_of(event).map(callback::Queue::Studio)
+}
+
+/// Queues `callback` if the instance's studio system is deferring callbacks, and runs it straight away otherwise.
+fn defer_event<F>(
+    name: &'static str,
+    event: fmod::studio::EventInstance,
+    callback: F,
+) -> fmod::Result<()>
+where
+    F: FnOnce(RbEventInstance) -> Result<()> + Send + 'static,
+{
+    let stamp = extern_struct_storage::stamp(event);
+    defer_with_stamp(studio_queue(event), stamp, name, event, callback)
+}
+
+// `stamp` is the instance's wrapper when FMOD fired the callback. if the instance has been destroyed since (and its
+// handle maybe reused) the callback is dropped, instead of wrapping a dead handle or someone else's instance
+fn defer_with_stamp<F>(
+    queue: Option<callback::Queue>,
+    stamp: Option<u64>,
+    name: &'static str,
+    event: fmod::studio::EventInstance,
+    callback: F,
+) -> fmod::Result<()>
+where
+    F: FnOnce(RbEventInstance) -> Result<()> + Send + 'static,
+{
+    callback::defer(queue, name, &event, move |_| {
+        let alive = match stamp {
+            Some(stamp) => extern_struct_storage::stamp(event) == Some(stamp),
+            None => event.is_valid(),
+        };
+        if !alive {
+            return Ok(());
+        }
+        callback(event.into_ruby()?)
+    })
+}
+
 impl EventInstanceCallback {
-    fn get_callback(inst: RbEventInstance) -> magnus::Value {
-        let callback: magnus::Value = inst.ivar_get("__callback").unwrap();
+    fn get_callback(inst: RbEventInstance) -> Result<magnus::Value> {
+        let callback: magnus::Value = inst.ivar_get("__callback")?;
         if callback.is_nil() {
-            let desc: RbEventDescription = inst.funcall("get_description", ()).unwrap();
-            desc.ivar_get("__callback").unwrap()
+            let desc: RbEventDescription = inst.funcall("get_description", ())?;
+            desc.ivar_get("__callback")
         } else {
-            callback
+            Ok(callback)
         }
     }
 }
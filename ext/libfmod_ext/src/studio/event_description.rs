@@ -81,10 +81,30 @@ impl EventDescription {
         }
 
         rb_self.ivar_set("__callback", callback)?;
+        rb_self.ivar_set("__callback_mask", u32::from(mask))?;
         instance
             .set_callback::<EventInstanceCallback>(mask)
             .into_ruby()
     }
+
+    fn apply_callback_mask(rb_self: magnus::Value, mask: u32) -> Result<()> {
+        let rb_self: RbEventDescription = magnus::TryConvert::try_convert(rb_self)?;
+        let instance: fmod::studio::EventDescription = rb_self.from_ruby()?;
+        instance
+            .set_callback::<EventInstanceCallback>(mask.into())
+            .into_ruby()
+    }
+
+    fn on(rb_self: RbEventDescription, event: magnus::Symbol) -> Result<magnus::Value> {
+        let ruby = magnus::Ruby::get().unwrap();
+        crate::listeners::subscribe(
+            rb_self,
+            event,
+            ruby.block_proc()?,
+            super::event_callback::EVENTS,
+            Self::apply_callback_mask,
+        )
+    }
 }
 
 extern_struct_bind! {
@@ -100,6 +120,7 @@ extern_struct_bind! {
         fn get_userdata -> 0;
         fn set_userdata -> 1;
         fn set_callback -> 2;
+        fn on -> 1;
         fn get_id -> 0;
         fn get_length -> 0;
         fn get_path -> 0;
@@ -78,11 +78,42 @@ impl EventInstance {
         }
 
         rb_self.ivar_set("__callback", callback)?;
+        rb_self.ivar_set("__callback_mask", u32::from(mask))?;
         instance
             .set_callback::<EventInstanceCallback>(mask)
             .into_ruby()
     }
 
+    fn apply_callback_mask(rb_self: magnus::Value, mask: u32) -> Result<()> {
+        let rb_self: RbEventInstance = magnus::TryConvert::try_convert(rb_self)?;
+        let instance: fmod::studio::EventInstance = rb_self.from_ruby()?;
+        instance
+            .set_callback::<EventInstanceCallback>(mask.into())
+            .into_ruby()
+    }
+
+    fn on(rb_self: RbEventInstance, event: magnus::Symbol) -> Result<magnus::Value> {
+        let ruby = magnus::Ruby::get().unwrap();
+        crate::listeners::subscribe(
+            rb_self,
+            event,
+            ruby.block_proc()?,
+            super::event_callback::EVENTS,
+            Self::apply_callback_mask,
+        )
+    }
+
+    fn set_owned_by_ruby(rb_self: RbEventInstance, owned: bool) -> Result<()> {
+        let instance: fmod::studio::EventInstance = rb_self.from_ruby()?;
+        crate::extern_struct_storage::set_owned(instance, rb_self, owned);
+        Ok(())
+    }
+
+    fn is_owned_by_ruby(rb_self: RbEventInstance) -> Result<bool> {
+        let instance: fmod::studio::EventInstance = rb_self.from_ruby()?;
+        Ok(crate::extern_struct_storage::is_owned(instance))
+    }
+
     fn get_userdata(rb_self: RbEventInstance) -> Result<magnus::Value> {
         let userdata: magnus::Value = rb_self.ivar_get("__userdata")?;
         if userdata.is_nil() {
@@ -132,8 +163,11 @@ extern_struct_bind! {
       fn get_userdata -> 0;
       fn set_userdata -> 1;
       fn set_callback -> 2;
+      fn on -> 1;
       fn get_description -> 0;
       fn release -> 0;
+      fn set_owned_by_ruby -> 1;
+      fn is_owned_by_ruby -> 0;
       fn is_valid -> 0;
       fn set_parameter_by_name -> 3;
       fn set_parameter_by_name_with_label -> 3;
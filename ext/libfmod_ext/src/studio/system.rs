@@ -82,8 +82,18 @@ impl System {
 
     fn release(rb_self: RbSystem) -> Result<()> {
         let system: fmod::studio::System = rb_self.from_ruby()?;
-        unsafe { system.release() }.into_ruby()?;
-        crate::extern_struct_storage::remove(system);
+        // the core system goes away with the studio system, so look it up first
+        let core_system = system.get_core_system().ok();
+        // anything already queued is delivered while its handles still exist, and anything fired during release
+        // (like `destroyed`) is delivered straight away
+        crate::callback::set_deferred(crate::callback::Queue::Studio(system), false);
+        if let Some(core_system) = core_system {
+            crate::callback::set_deferred(crate::callback::Queue::System(core_system), false);
+        }
+        // `destroyed` callbacks fire during release and need the GVL
+        unsafe { thread::without_gvl_no_ubf(|| system.release()) }.into_ruby()?;
+        // everything created through this system is gone now too
+        crate::extern_struct_storage::remove_with_children(system);
         crate::extern_struct_storage::cleanup();
         Ok(())
     }
@@ -91,8 +101,13 @@ impl System {
     fn update(rb_self: RbSystem) -> Result<()> {
         let system: fmod::studio::System = rb_self.from_ruby()?;
         unsafe { thread::without_gvl_no_ubf(|| system.update()) }.into_ruby()?;
-        crate::extern_struct_storage::cleanup();
-        Ok(())
+        // studio drives its core system, so its core callbacks get drained here too
+        crate::callback::drain(crate::callback::Queue::Studio(system));
+        if let Ok(core_system) = system.get_core_system() {
+            crate::callback::drain(crate::callback::Queue::System(core_system));
+        }
+        crate::extern_struct_storage::cleanup_step();
+        crate::callback::raise_pending_error()
     }
 
     fn load_bank_file(
@@ -101,6 +116,7 @@ impl System {
         flags: LoadBankFlags,
     ) -> Result<RbBank> {
         let system: fmod::studio::System = rb_self.from_ruby()?;
+        let _scope = crate::extern_struct_storage::OwnerScope::enter(system);
         let filename = filename.from_ruby()?;
         let flags = flags.from_ruby()?;
 
@@ -113,10 +129,15 @@ impl System {
         flags: LoadBankFlags,
     ) -> Result<RbBank> {
         let system: fmod::studio::System = rb_self.from_ruby()?;
-        let buffer = buffer.from_ruby()?;
+        let _scope = crate::extern_struct_storage::OwnerScope::enter(system);
+        // FMOD copies the bank data while loading, but that happens without the GVL where ruby could be modifying or
+        // compacting the string. so we load from our own copy instead
+        let buffer: &[u8] = buffer.from_ruby()?;
+        let buffer = buffer.to_vec();
         let flags = flags.from_ruby()?;
 
-        unsafe { thread::without_gvl_no_ubf(|| system.load_bank_memory(buffer, flags)) }.into_ruby()
+        unsafe { thread::without_gvl_no_ubf(|| system.load_bank_memory(&buffer, flags)) }
+            .into_ruby()
     }
 
     fn flush_commands(rb_self: RbSystem) -> Result<()> {
@@ -156,9 +177,43 @@ impl System {
         }
 
         rb_self.ivar_set("__callback", callback)?;
+        rb_self.ivar_set("__callback_mask", u32::from(mask))?;
         system.set_callback::<SystemCallback>(mask).into_ruby()
     }
 
+    fn apply_callback_mask(rb_self: magnus::Value, mask: u32) -> Result<()> {
+        let rb_self: RbSystem = magnus::TryConvert::try_convert(rb_self)?;
+        let system: fmod::studio::System = rb_self.from_ruby()?;
+        system
+            .set_callback::<SystemCallback>(mask.into())
+            .into_ruby()
+    }
+
+    fn on(rb_self: RbSystem, event: magnus::Symbol) -> Result<magnus::Value> {
+        let ruby = magnus::Ruby::get().unwrap();
+        crate::listeners::subscribe(
+            rb_self,
+            event,
+            ruby.block_proc()?,
+            super::system_callback::EVENTS,
+            Self::apply_callback_mask,
+        )
+    }
+
+    // the core system studio owns is deferred along with it, since studio is what updates it
+    fn set_deferred_callbacks(rb_self: RbSystem, deferred: bool) -> Result<()> {
+        let system: fmod::studio::System = rb_self.from_ruby()?;
+        let core_system = system.get_core_system().map_err(crate::error::from_fmod)?;
+        crate::callback::set_deferred(crate::callback::Queue::Studio(system), deferred);
+        crate::callback::set_deferred(crate::callback::Queue::System(core_system), deferred);
+        Ok(())
+    }
+
+    fn get_deferred_callbacks(rb_self: RbSystem) -> Result<bool> {
+        let system: fmod::studio::System = rb_self.from_ruby()?;
+        Ok(crate::callback::is_deferred(crate::callback::Queue::Studio(system)))
+    }
+
     // have to handwrite this one unfortunately, slice conversion is a bit tricky
     // if set_parameters_by_ids took an AsRef<T> though...
     // FIXME do the above
@@ -205,6 +260,9 @@ extern_struct_bind! {
     fn get_userdata -> 0;
     fn set_userdata -> 1;
     fn set_callback -> 2;
+    fn on -> 1;
+    fn set_deferred_callbacks -> 1;
+    fn get_deferred_callbacks -> 0;
     fn start_command_capture -> 2;
     fn stop_command_capture -> 0;
     fn load_command_replay -> 2;
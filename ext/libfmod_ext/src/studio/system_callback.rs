@@ -3,7 +3,7 @@
 // This Source Code Form is subject to the terms of the Mozilla Public
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
-use crate::{callback, IntoRuby, Result};
+use crate::{callback, listeners, IntoRuby, Result};
 use magnus::{
     prelude::*,
     value::{InnerValue, Opaque},
@@ -14,33 +14,63 @@ use super::{bank::RbBank, system::RbSystem};
 
 static CLASS: OnceCell<Opaque<magnus::RClass>> = OnceCell::new();
 
+/// Events that can be listened to with `Studio::System#on`.
+pub const EVENTS: listeners::Events = &[
+    (
+        "preupdate",
+        fmod::ffi::FMOD_STUDIO_SYSTEM_CALLBACK_PREUPDATE,
+    ),
+    (
+        "postupdate",
+        fmod::ffi::FMOD_STUDIO_SYSTEM_CALLBACK_POSTUPDATE,
+    ),
+    (
+        "bank_unload",
+        fmod::ffi::FMOD_STUDIO_SYSTEM_CALLBACK_BANK_UNLOAD,
+    ),
+    (
+        "liveupdate_connected",
+        fmod::ffi::FMOD_STUDIO_SYSTEM_CALLBACK_LIVEUPDATE_CONNECTED,
+    ),
+    (
+        "liveupdate_disconnected",
+        fmod::ffi::FMOD_STUDIO_SYSTEM_CALLBACK_LIVEUPDATE_DISCONNECTED,
+    ),
+];
+
 pub struct SystemCallback;
 
 impl fmod::studio::SystemCallback for SystemCallback {
     fn preupdate(system: fmod::studio::System, _: *mut std::ffi::c_void) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback.funcall("preupdate", (system, userdata)).unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            Some(callback::Queue::Studio(system)),
+            "preupdate",
+            &system,
+            move |_| {
+                let system = system.into_ruby()?;
+                let userdata: magnus::Value = system.ivar_get("__userdata")?;
+                let callback: magnus::Value = system.ivar_get("__callback")?;
+                let _: magnus::Value =
+                    listeners::dispatch(callback, "preupdate", (system, userdata))?;
+                Ok(())
+            },
+        )
     }
 
     fn postupdate(system: fmod::studio::System, _: *mut std::ffi::c_void) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback.funcall("postupdate", (system, userdata)).unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            Some(callback::Queue::Studio(system)),
+            "postupdate",
+            &system,
+            move |_| {
+                let system = system.into_ruby()?;
+                let userdata: magnus::Value = system.ivar_get("__userdata")?;
+                let callback: magnus::Value = system.ivar_get("__callback")?;
+                let _: magnus::Value =
+                    listeners::dispatch(callback, "postupdate", (system, userdata))?;
+                Ok(())
+            },
+        )
     }
 
     fn bank_unload(
@@ -48,55 +78,58 @@ impl fmod::studio::SystemCallback for SystemCallback {
         bank: fmod::studio::Bank,
         _: *mut std::ffi::c_void,
     ) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let bank = bank.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback
-                .funcall("bank_unload", (system, bank, userdata))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            Some(callback::Queue::Studio(system)),
+            "bank_unload",
+            &system,
+            move |_| {
+                let system = system.into_ruby()?;
+                let bank = bank.into_ruby()?;
+                let userdata: magnus::Value = system.ivar_get("__userdata")?;
+                let callback: magnus::Value = system.ivar_get("__callback")?;
+                let _: magnus::Value =
+                    listeners::dispatch(callback, "bank_unload", (system, bank, userdata))?;
+                Ok(())
+            },
+        )
     }
 
     fn liveupdate_connected(
         system: fmod::studio::System,
         _: *mut std::ffi::c_void,
     ) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback
-                .funcall("liveupdate_connected", (system, userdata))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            Some(callback::Queue::Studio(system)),
+            "liveupdate_connected",
+            &system,
+            move |_| {
+                let system = system.into_ruby()?;
+                let userdata: magnus::Value = system.ivar_get("__userdata")?;
+                let callback: magnus::Value = system.ivar_get("__callback")?;
+                let _: magnus::Value =
+                    listeners::dispatch(callback, "liveupdate_connected", (system, userdata))?;
+                Ok(())
+            },
+        )
     }
 
     fn liveupdate_disconnected(
         system: fmod::studio::System,
         _: *mut std::ffi::c_void,
     ) -> fmod::Result<()> {
-        let (sender, reciever) = oneshot::channel::<()>();
-        callback::process(move |_| {
-            let system = system.into_ruby().unwrap();
-            let userdata: magnus::Value = system.ivar_get("__userdata").unwrap();
-            let callback: magnus::RObject = system.ivar_get("__callback").unwrap();
-            let _: magnus::Value = callback
-                .funcall("liveupdate_disconnected", (system, userdata))
-                .unwrap();
-            sender.send(()).unwrap();
-        });
-        let _ = reciever.recv();
-        Ok(())
+        callback::defer(
+            Some(callback::Queue::Studio(system)),
+            "liveupdate_disconnected",
+            &system,
+            move |_| {
+                let system = system.into_ruby()?;
+                let userdata: magnus::Value = system.ivar_get("__userdata")?;
+                let callback: magnus::Value = system.ivar_get("__callback")?;
+                let _: magnus::Value =
+                    listeners::dispatch(callback, "liveupdate_disconnected", (system, userdata))?;
+                Ok(())
+            },
+        )
     }
 }
 
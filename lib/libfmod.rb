@@ -2,4 +2,573 @@
 
 require_relative "libfmod/libfmod_ext"
 
+module FMOD
+  # Restarts the callback thread in forked children, since threads don't survive a fork.
+  module ForkHook
+    def _fork
+      pid = super
+      FMOD.__after_fork if pid.zero?
+      pid
+    end
+  end
 
+  class SoundBuilder
+    # Every keyword +open+ (and the other constructors), +with_options+ and +preset+ take, besides +loop_points+.
+    # Each one calls the +with_+ method of the same name, with an array value being splatted for methods that take
+    # more than one argument (like +open_raw: [2, 44_100, FMOD::SoundFormat::PCM16]+).
+    OPTIONS = %i[
+      file_offset open_raw mode decode_buffer_size initial_subsound inclusion_list dls_name encryption_key
+      max_polyphony suggested_sound_type file_buffer_size channel_order initial_sound_group initial_seek_position
+      ignore_set_filesystem min_midi_granularity non_block_thread_id fsb_guid
+    ].freeze
+
+    # The keywords +open_user+ takes to describe the sound, rather than settings from +OPTIONS+.
+    USER_KEYWORDS = %i[channels frequency format length buffer_size].freeze
+
+    # Settings saved with +SoundBuilder.preset+, that can be used to open any number of sounds.
+    Preset = Struct.new(:name, :options) do
+      %i[open open_memory open_memory_point open_io].each do |method|
+        define_method(method) do |source, **options|
+          SoundBuilder.public_send(method, source, **self.options.merge(options))
+        end
+      end
+
+      def open_user(**options, &block)
+        SoundBuilder.open_user(**self.options.merge(options), &block)
+      end
+    end
+
+    # Adds keywords to the constructors, and a +preset:+ keyword to start from a saved preset.
+    module Keywords
+      %i[open open_memory open_memory_point open_io].each do |method|
+        define_method(method) do |source, preset: nil, **options|
+          options = self.preset(preset).options.merge(options) if preset
+          SoundBuilder.check_options(options)
+
+          builder = super(source)
+          options.empty? ? builder : builder.with_options(**options)
+        end
+      end
+
+      def open_user(preset: nil, **options, &block)
+        options = self.preset(preset).options.merge(options) if preset
+        user_options = options.select { |key, _| USER_KEYWORDS.include?(key) }
+        options = options.reject { |key, _| USER_KEYWORDS.include?(key) }
+        SoundBuilder.check_options(options)
+
+        builder = super(**user_options, &block)
+        options.empty? ? builder : builder.with_options(**options)
+      end
+    end
+    singleton_class.prepend(Keywords)
+
+    # Sets the loop points the sound gets once it's created,
+    # as the arguments to +Sound#set_loop_points+: +[start, start_unit, end, end_unit]+.
+    attr_accessor :loop_points
+
+    class << self
+      # With options, saves them as a preset called +name+. Without, returns the preset called +name+.
+      def preset(name, **options)
+        @presets ||= {}
+        return @presets.fetch(name) { raise ArgumentError, "unknown preset: #{name.inspect}" } if options.empty?
+
+        check_options(options)
+        @presets[name] = Preset.new(name, options.freeze).freeze
+      end
+
+      def presets
+        (@presets || {}).dup
+      end
+
+      # Raises an ArgumentError listing every key of +options+ that isn't a setting.
+      def check_options(options)
+        unknown = options.keys - OPTIONS - [:loop_points]
+        return if unknown.empty?
+
+        raise ArgumentError, "unknown keyword#{"s" if unknown.size > 1}: #{unknown.map(&:inspect).join(", ")}"
+      end
+    end
+
+    # Applies every setting in +options+, see +OPTIONS+.
+    def with_options(**options)
+      SoundBuilder.check_options(options)
+      options.each do |key, value|
+        next self.loop_points = value if key == :loop_points
+
+        name = :"with_#{key}"
+        if method(name).arity == 1
+          public_send(name, value)
+        else
+          public_send(name, *value)
+        end
+      end
+      self
+    end
+
+    # Applies the builder's +loop_points+ to sounds created from it.
+    module LoopPoints
+      %i[create_sound create_stream].each do |method|
+        define_method(method) do |builder|
+          sound = super(builder)
+          sound.set_loop_points(*builder.loop_points) if builder.loop_points
+          sound
+        end
+      end
+
+      def create_sound_async(builder)
+        future = super
+        loop_points = builder.loop_points
+        future.on_ready { |sound, error| sound.set_loop_points(*loop_points) unless error } if loop_points
+        future
+      end
+    end
+    FMOD::System.prepend(LoopPoints)
+  end
+
+  # Adds block forms to methods that create something that has to be released.
+  # The new object is yielded, and released once the block returns or raises.
+  module ReleaseAfterBlock
+    def self.wrap(klass, *methods)
+      klass.prepend(Module.new do
+        methods.each do |method|
+          define_method(method) do |*args, &block|
+            object = super(*args)
+            return object unless block
+
+            begin
+              block.call(object)
+            ensure
+              object.release
+            end
+          end
+        end
+      end)
+    end
+
+    wrap FMOD::System,
+         :create_sound, :create_stream, :create_dsp_by_type, :create_dsp_by_plugin,
+         :create_channel_group, :create_sound_group, :create_geometry, :create_reverb_3d
+    wrap FMOD::Studio::EventDescription, :create_instance
+    wrap FMOD::Studio::System, :load_command_replay
+  end
+
+  class Sound
+    # Names for every +FMOD::TimeUnit+, used as the keys of +info[:length]+.
+    TIME_UNITS = {
+      ms: FMOD::TimeUnit::MS,
+      pcm: FMOD::TimeUnit::PCM,
+      pcm_bytes: FMOD::TimeUnit::PCMBytes,
+      raw_bytes: FMOD::TimeUnit::RawBytes,
+      pcm_fraction: FMOD::TimeUnit::PCMFraction,
+      mod_order: FMOD::TimeUnit::ModOrder,
+      mod_row: FMOD::TimeUnit::ModRow,
+      mod_pattern: FMOD::TimeUnit::ModPattern
+    }.freeze
+
+    # Everything about the sound worth showing at a glance, as a hash.
+    # Lengths in units that don't apply to the sound (like +mod_row+ for anything that isn't a tracker module) are nil.
+    def info
+      loop_start, loop_end = get_loop_points(FMOD::TimeUnit::PCM, FMOD::TimeUnit::PCM)
+      {
+        name: get_name,
+        format: get_format,
+        length: TIME_UNITS.transform_values do |unit|
+          get_length(unit)
+        rescue FMOD::Error
+          nil
+        end,
+        sub_sound_count: get_sub_sound_count,
+        loop: { count: get_loop_count, start: loop_start, end: loop_end },
+        mode: get_mode
+      }
+    end
+
+    # Yields the whole sound decoded to PCM, +bytes+ at a time, starting from the beginning.
+    # The sound should be opened with +FMOD::Mode::OPEN_ONLY+, and not be playing.
+    def each_chunk(bytes = 64 * 1024)
+      return enum_for(:each_chunk, bytes) unless block_given?
+
+      seek_data(0)
+      while (chunk = read_data(bytes))
+        yield chunk
+      end
+      self
+    end
+  end
+
+  class DSP
+    # Every parameter's +DspParameterInfo+, in index order.
+    def parameters
+      Array.new(get_parameter_count) { |index| get_parameter_info(index) }
+    end
+
+    # Reads a parameter by index or name, whatever its type.
+    # Data parameters are decoded like +get_parameter_data_value+ does.
+    def [](parameter)
+      index, info = parameter_index(parameter)
+      case info.type
+      when FMOD::DspParameterType::Float then get_parameter_float(index)
+      when FMOD::DspParameterType::Int then get_parameter_int(index)
+      when FMOD::DspParameterType::Bool then get_parameter_bool(index)
+      else get_parameter_data_value(index)
+      end
+    end
+
+    # The spectrum of an FFT DSP: +length+ bins per channel in +channels+, each from 0 to 1,
+    # and the +dominant_frequency+ (in Hz) when the DSP reports one.
+    def get_fft_spectrum
+      spectrum = get_parameter_data_value(get_data_parameter_index(FMOD::DspParameterDataType::FFT))
+      dominant = self[:dominant_frequency] if is_a?(FMOD::DSP::FFT)
+      FFTSpectrum.new(spectrum[:length], spectrum[:channels], dominant)
+    end
+
+    # Sets a parameter by index or name, whatever its type.
+    # Numbers are checked against the parameter's range, and int parameters also take one of their value names.
+    def []=(parameter, value)
+      index, info = parameter_index(parameter)
+      case info.type
+      when FMOD::DspParameterType::Float
+        check_parameter_range(info, value)
+        set_parameter_float(index, value)
+      when FMOD::DspParameterType::Int
+        unless value.is_a?(Integer)
+          position = info.value_names&.index(value.to_s)
+          raise ArgumentError, "unknown value #{value.inspect} for #{info.name}" unless position
+
+          value = info.min + position
+        end
+        check_parameter_range(info, value)
+        set_parameter_int(index, value)
+      when FMOD::DspParameterType::Bool
+        raise TypeError, "#{info.name} is a bool parameter, got #{value.inspect}" unless [true, false].include?(value)
+
+        set_parameter_bool(index, value)
+      else
+        set_parameter_data(index, value)
+      end
+    end
+
+    # The +DspParameterInfo+ of a parameter, by index or name. Its +label+ is the parameter's unit.
+    def parameter_info(parameter)
+      parameter_index(parameter).last
+    end
+
+    FFTSpectrum = Struct.new(:length, :channels, :dominant_frequency)
+
+    # Names for the parameters of each built-in effect, in index order, from FMOD's headers.
+    # Each effect gets a subclass with an accessor per parameter, which +System#create_dsp_by_type+ returns.
+    # (+release+ is spelt +release_time+, so it doesn't hide +DSP#release+, and a +2D+/+3D+ prefix goes at the end.)
+    # Every +DspType+ has an entry, the ones without parameters (like +Mixer+ and plugins) just get no accessors.
+    EFFECTS = {
+      Unknown: [:Unknown, []],
+      Mixer: [:Mixer, []],
+      Oscillator: [:Oscillator, %i[type rate]],
+      LowPass: [:Lowpass, %i[cutoff resonance]],
+      ITLowPass: [:ItLowpass, %i[cutoff resonance]],
+      HighPass: [:Highpass, %i[cutoff resonance]],
+      Echo: [:Echo, %i[delay feedback dry_level wet_level]],
+      Fader: [:Fader, %i[gain overall_gain]],
+      Flange: [:Flange, %i[mix depth rate]],
+      Distortion: [:Distortion, %i[level]],
+      Normalize: [:Normalize, %i[fade_time threshold max_amp]],
+      Limiter: [:Limiter, %i[release_time ceiling maximizer_gain mode]],
+      ParamEq: [:ParamEq, %i[center bandwidth gain]],
+      PitchShift: [:PitchShift, %i[pitch fft_size overlap max_channels]],
+      Chorus: [:Chorus, %i[mix rate depth]],
+      VSTPlugin: [:VstPlugin, []],
+      WinampPlugin: [:WinampPlugin, []],
+      ITEcho: [:ItEcho, %i[wet_dry_mix feedback left_delay right_delay pan_delay]],
+      Compressor: [:Compressor, %i[threshold ratio attack release_time gain_makeup use_sidechain linked]],
+      SFXReverb: [:SfxReverb, %i[
+        decay_time early_delay late_delay hf_reference hf_decay_ratio diffusion density
+        low_shelf_frequency low_shelf_gain high_cut early_late_mix wet_level dry_level
+      ]],
+      LowPassSimple: [:LowpassSimple, %i[cutoff]],
+      Delay: [:Delay, Array.new(16) { |channel| :"channel_#{channel}_delay" } + %i[max_delay]],
+      Tremolo: [:Tremolo, %i[frequency depth shape skew duty square phase spread]],
+      LADSPAPlugin: [:LadspaPlugin, []],
+      Send: [:Send, %i[return_id level]],
+      Return: [:Return, %i[id input_speaker_mode]],
+      HighPassSimple: [:HighpassSimple, %i[cutoff]],
+      Pan: [:Pan, %i[
+        mode stereo_position_2d direction_2d extent_2d rotation_2d lfe_level_2d stereo_mode_2d stereo_separation_2d
+        stereo_axis_2d enabled_speakers position_3d rolloff_3d min_distance_3d max_distance_3d extent_mode_3d
+        sound_size_3d min_extent_3d pan_blend_3d lfe_upmix_enabled overall_gain surround_speaker_mode height_blend_2d
+        attenuation_range override_range
+      ]],
+      ThreeEQ: [:ThreeEq, %i[low_gain mid_gain high_gain low_crossover high_crossover crossover_slope]],
+      FFT: [:Fft, %i[window_size window_type spectrum_data dominant_frequency]],
+      LoudnessMeter: [:LoudnessMeter, %i[state weighting info]],
+      EnvelopeFollower: [:EnvelopeFollower, %i[attack release_time envelope use_sidechain]],
+      ConvolutionReverb: [:ConvolutionReverb, %i[impulse_response wet dry linked]],
+      ChannelMix: [:ChannelMix, %i[output_grouping] +
+        Array.new(32) { |channel| :"channel_#{channel}_gain" } +
+        Array.new(32) { |channel| :"channel_#{channel}_output" }],
+      ObjectPan: [:ObjectPan, %i[
+        position_3d rolloff_3d min_distance_3d max_distance_3d extent_mode_3d sound_size_3d min_extent_3d overall_gain
+        output_gain attenuation_range override_range
+      ]],
+      MultibandEQ: [:MultibandEq, %w[a b c d e].flat_map do |band|
+        %i[filter frequency q gain].map { |parameter| :"#{band}_#{parameter}" }
+      end]
+    }.freeze
+
+    EFFECTS.each do |name, (type, parameters)|
+      klass = Class.new(self) do
+        const_set(:PARAMETERS, parameters.freeze)
+
+        parameters.each_with_index do |parameter, index|
+          define_method(parameter) { self[index] }
+          define_method(:"#{parameter}=") { |value| self[index] = value }
+        end
+      end
+      const_set(name, klass)
+      register_type(FMOD::DspType.const_get(type), klass)
+    end
+
+    private
+
+    def parameter_index(parameter)
+      if parameter.is_a?(Symbol) && self.class.const_defined?(:PARAMETERS)
+        index = self.class::PARAMETERS.index(parameter)
+        return [index, get_parameter_info(index)] if index
+      end
+
+      if parameter.is_a?(Integer)
+        raise IndexError, "parameter #{parameter} is out of range" unless (0...get_parameter_count).cover?(parameter)
+
+        return [parameter, get_parameter_info(parameter)]
+      end
+
+      name = parameter.to_s
+      parameters.each_with_index do |info, index|
+        return [index, info] if info.name.casecmp?(name)
+      end
+      raise ArgumentError, "unknown parameter #{parameter.inspect}"
+    end
+
+    def check_parameter_range(info, value)
+      raise TypeError, "#{info.name} takes a number, got #{value.inspect}" unless value.is_a?(Numeric)
+      return if value.between?(info.min, info.max)
+
+      raise RangeError, "#{value} is out of range for #{info.name} (#{info.min}..#{info.max})"
+    end
+  end
+
+  # A list of subsounds of a stream (like the phrases of a line of dialog in an FSB), played back to back without gaps.
+  #
+  #   sentence = FMOD::Sentence.new(stream).add(0).add(2, 2, 5)
+  #   channel = sentence.play(system)
+  class Sentence
+    attr_reader :sound, :indices
+
+    def initialize(sound, indices = [])
+      @sound = sound
+      @indices = []
+      add(*indices)
+    end
+
+    # Appends subsounds to the sentence, raising an IndexError for any the sound doesn't have.
+    def add(*indices)
+      count = sound.get_sub_sound_count
+      indices.each do |index|
+        raise IndexError, "subsound #{index} is out of range (sound has #{count})" unless (0...count).cover?(index)
+      end
+      @indices.concat(indices)
+      self
+    end
+    alias << add
+
+    # Makes the sound play the sentence.
+    def apply
+      sound.set_subsound_sentence(indices)
+      self
+    end
+
+    # Plays the sentence on a new channel. The channel keeps the sentence (and so the stream) alive while it exists.
+    def play(system, channel_group = nil, paused: false)
+      apply
+      channel = system.play_sound(sound, channel_group, paused)
+      channel.instance_variable_set(:@__sentence, self)
+      channel
+    end
+  end
+
+  class SoundFuture
+    # Waits until the sound has loaded, or +timeout+ seconds have passed. Returns whether it loaded.
+    # Under a Fiber scheduler only the current fiber waits, otherwise the whole thread does (without the GVL).
+    def wait(timeout = nil)
+      return true if ready?
+      return __wait(timeout) unless Fiber.respond_to?(:scheduler) && Fiber.scheduler && !Fiber.blocking?
+
+      mutex = Mutex.new
+      condition = ConditionVariable.new
+      on_ready { mutex.synchronize { condition.broadcast } }
+
+      deadline = timeout && Process.clock_gettime(Process::CLOCK_MONOTONIC) + timeout
+      mutex.synchronize do
+        until ready?
+          remaining = deadline && deadline - Process.clock_gettime(Process::CLOCK_MONOTONIC)
+          return false if remaining && remaining <= 0
+
+          condition.wait(mutex, remaining)
+        end
+      end
+      true
+    end
+
+    # Waits for the sound to load and returns it, raising the error it failed to load with.
+    def value
+      wait
+      __value
+    end
+  end
+
+  # A snapshot of the DSPs feeding into a DSP (usually a channel group's head), for debugging the mixer.
+  #
+  #   graph = system.dsp_graph
+  #   File.write("mixer.dot", graph.to_dot)
+  #
+  # Edges point the way audio flows, from an input to the DSP it feeds into.
+  class DSPGraph
+    # +FMOD_CHANNELCONTROL_DSP_HEAD+, the DSP everything on a channel group is mixed into.
+    HEAD = -1
+
+    Node = Struct.new(:id, :dsp, :type, :active, :bypass, :wet_dry, :cpu) do
+      def to_h
+        { id: id, type: type, active: active, bypass: bypass, wet_dry: wet_dry, cpu: cpu }
+      end
+    end
+
+    Edge = Struct.new(:from, :to, :type, :mix) do
+      def to_h
+        { from: from, to: to, type: type, mix: mix }
+      end
+    end
+
+    attr_reader :nodes, :edges
+
+    # Walks every input of +head+ (and their inputs, and so on). Each DSP is only visited once.
+    def self.walk(head)
+      new.tap { |graph| graph.send(:visit, head) }
+    end
+
+    def initialize
+      @nodes = []
+      @edges = []
+      @ids = {}
+    end
+
+    def root
+      nodes.first
+    end
+
+    def to_h
+      { nodes: nodes.map(&:to_h), edges: edges.map(&:to_h) }
+    end
+
+    def to_json(*args)
+      require "json"
+      to_h.to_json(*args)
+    end
+
+    def to_dot
+      lines = ["digraph dsp {", "  rankdir=LR;", "  node [shape=box];"]
+      nodes.each do |node|
+        label = "#{node.type}\\ncpu #{node.cpu[:exclusive]}us / #{node.cpu[:inclusive]}us"
+        style = []
+        style << "dashed" if node.bypass
+        style << "filled" unless node.active
+        attributes = %(label="#{escape(label)}")
+        attributes += %(, style="#{style.join(',')}") unless style.empty?
+        attributes += ", fillcolor=gray" unless node.active
+        lines << "  n#{node.id} [#{attributes}];"
+      end
+      edges.each do |edge|
+        attributes = %(label="#{format('%.2f', edge.mix)}")
+        attributes += ", style=dashed" unless edge.type == :Standard
+        lines << "  n#{edge.from} -> n#{edge.to} [#{attributes}];"
+      end
+      lines << "}"
+      lines.join("\n") << "\n"
+    end
+
+    private
+
+    def visit(dsp)
+      return @ids[dsp] if @ids.key?(dsp)
+
+      id = @ids[dsp] = nodes.size
+      nodes << node(id, dsp)
+      dsp.get_input_count.times do |index|
+        input, connection = dsp.get_input(index)
+        edges << Edge.new(visit(input), id, constant_name(FMOD::DspConnectionType, connection.get_type),
+                          connection.get_mix)
+      end
+      id
+    end
+
+    def node(id, dsp)
+      pre_wet, post_wet, dry = dsp.get_wet_dry_mix
+      exclusive, inclusive = dsp.get_cpu_usage
+      Node.new(id, dsp, constant_name(FMOD::DspType, dsp.get_type), dsp.get_active, dsp.get_bypass,
+               { pre_wet: pre_wet, post_wet: post_wet, dry: dry }, { exclusive: exclusive, inclusive: inclusive })
+    end
+
+    def constant_name(mod, value)
+      mod.constants.find { |name| mod.const_get(name) == value } || value
+    end
+
+    def escape(string)
+      string.gsub('"', '\"')
+    end
+  end
+
+  class ChannelGroup
+    # The DSPs mixed into this channel group, starting from its head. See +DSPGraph+.
+    def dsp_graph
+      DSPGraph.walk(get_dsp(DSPGraph::HEAD))
+    end
+  end
+
+  class System
+    # The whole mixer, starting from the master channel group. See +DSPGraph+.
+    def dsp_graph
+      get_master_channel_group.dsp_graph
+    end
+  end
+
+  class ExternStructStorage
+    class << self
+      # Print a leak report when the process exits.
+      attr_accessor :report_leaks_at_exit
+
+      # Yields every live wrapper, optionally only those of one type (one of the keys from +stats+).
+      def each(type = nil, &block)
+        return enum_for(:each, type) unless block_given?
+
+        __values(type).each(&block)
+      end
+
+      # Writes every Sound, DSP and EventInstance that was never released to +io+.
+      # Enable +debug+ before creating them to include where each one was created.
+      def report_leaks(io = $stderr)
+        leaks.each do |leak|
+          io.puts "FMOD: #{leak[:type]} #{leak[:object].inspect} was never released"
+          leak[:backtrace]&.each { |line| io.puts "    #{line}" }
+        end
+      end
+    end
+  end
+end
+
+Process.singleton_class.prepend(FMOD::ForkHook) if Process.respond_to?(:_fork)
+
+# a forked child inherits the parent's handles, and releasing those would release the parent's systems
+fmod_pid = Process.pid
+at_exit do
+  next unless Process.pid == fmod_pid
+
+  FMOD::ExternStructStorage.report_leaks if FMOD::ExternStructStorage.report_leaks_at_exit
+  FMOD.shutdown
+end
@@ -0,0 +1,38 @@
+# frozen_string_literal: true
+
+require_relative "test_helper"
+
+class CallbackTimeoutTest < Minitest::Test
+  include FMODTestHelper
+
+  def setup
+    FMOD.reset_callback_stats
+  end
+
+  def teardown
+    FMOD.set_callback_timeout(:end, nil)
+    super
+  end
+
+  def test_running_callback_is_abandoned_at_timeout
+    system = build_system
+    channel = system.play_dsp(system.create_dsp_by_type(FMOD::DspType::Oscillator), nil, false)
+
+    started = Queue.new
+    finished = Queue.new
+    channel.on(:end) do
+      started << true
+      sleep 0.5
+      finished << true
+    end
+    FMOD.set_callback_timeout(:end, 0.05)
+
+    elapsed = Benchmark.realtime { channel.stop }
+
+    assert started.pop
+    assert_operator elapsed, :<, 0.4, "FMOD waited for the callback to finish"
+    assert_equal 1, FMOD.callback_stats[:end][:stalls]
+    # the abandoned callback still runs to completion
+    assert finished.pop
+  end
+end
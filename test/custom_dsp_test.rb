@@ -0,0 +1,51 @@
+# frozen_string_literal: true
+
+require_relative "test_helper"
+
+class CustomDSPTest < Minitest::Test
+  include FMODTestHelper
+
+  def play_through(system, dsp)
+    channel = system.play_dsp(system.create_dsp_by_type(FMOD::DspType::Oscillator), nil, false)
+    channel.add_dsp(0, dsp)
+    channel
+  end
+
+  def test_block_is_called_with_each_mix_block
+    system = build_system
+    calls = Queue.new
+    dsp = system.create_custom_dsp(name: "Silence", parameters: [{ name: "Gain", default: 0.25 }]) do |in_buf, out_buf, length, channels, parameters|
+      calls << [in_buf.frozen?, in_buf.bytesize, length * channels * 4, parameters]
+      out_buf.replace("\0" * out_buf.bytesize)
+    end
+    play_through(system, dsp)
+
+    3.times { system.update }
+
+    frozen, bytesize, expected, parameters = calls.pop
+    assert frozen, "the input buffer should be frozen"
+    assert_equal expected, bytesize
+    assert_equal [0.25], parameters
+  end
+
+  def test_native_processor_parameters
+    system = build_system
+    dsp = system.create_custom_dsp(name: "Crusher", processor: :bitcrusher)
+    play_through(system, dsp)
+
+    assert_equal 2, dsp.get_parameter_count
+    dsp.set_parameter_float(0, 4.0)
+    3.times { system.update }
+
+    value, = dsp.get_parameter_float(0)
+    assert_in_delta 4.0, value
+  end
+
+  def test_processor_and_block_are_exclusive
+    system = build_system
+
+    assert_raises(ArgumentError) { system.create_custom_dsp(name: "Both", processor: :bitcrusher) { nil } }
+    assert_raises(ArgumentError) { system.create_custom_dsp(name: "Neither") }
+    assert_raises(ArgumentError) { system.create_custom_dsp(name: "Unknown", processor: :reverb) }
+  end
+end
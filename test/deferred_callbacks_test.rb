@@ -0,0 +1,45 @@
+# frozen_string_literal: true
+
+require_relative "test_helper"
+
+class DeferredCallbacksTest < Minitest::Test
+  include FMODTestHelper
+
+  def test_deferral_is_per_studio_system
+    deferred = build_studio_system
+    immediate = build_studio_system
+
+    deferred.set_deferred_callbacks(true)
+
+    assert deferred.get_deferred_callbacks
+    refute immediate.get_deferred_callbacks
+  end
+
+  def test_deferred_callbacks_run_from_update
+    deferred = build_studio_system
+    immediate = build_studio_system
+    deferred.set_deferred_callbacks(true)
+
+    threads = { deferred => [], immediate => [] }
+    threads.each_key do |system|
+      system.on(:postupdate) { threads[system] << Thread.current }
+    end
+
+    update_until { threads.values.none?(&:empty?) }
+
+    assert threads[deferred].all? { |thread| thread == Thread.current }
+    refute_includes threads[immediate], Thread.current
+  end
+
+  private
+
+  # studio updates asynchronously, so callbacks can take a few updates to show up
+  def update_until(timeout: 2)
+    deadline = Process.clock_gettime(Process::CLOCK_MONOTONIC) + timeout
+    until yield
+      flunk "timed out waiting for callbacks" if Process.clock_gettime(Process::CLOCK_MONOTONIC) > deadline
+      systems.each(&:update)
+      sleep 0.01
+    end
+  end
+end
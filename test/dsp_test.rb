@@ -0,0 +1,29 @@
+# frozen_string_literal: true
+
+require_relative "test_helper"
+
+class DSPTest < Minitest::Test
+  include FMODTestHelper
+
+  def test_every_dsp_type_has_a_class
+    FMOD::DspType.constants.each do |name|
+      klass = FMOD::DSP::CLASSES[FMOD::DspType.const_get(name)]
+
+      refute_nil klass, "FMOD::DspType::#{name} has no class"
+      assert_operator klass, :<, FMOD::DSP
+    end
+  end
+
+  def test_parameter_names_match_parameter_count
+    system = build_system
+
+    FMOD::DSP::EFFECTS.each do |name, (type, parameters)|
+      next if parameters.empty?
+
+      dsp = system.create_dsp_by_type(FMOD::DspType.const_get(type))
+      assert_instance_of FMOD::DSP.const_get(name), dsp
+      assert_equal parameters.size, dsp.get_parameter_count, "#{name} has the wrong number of parameter names"
+      dsp.release
+    end
+  end
+end
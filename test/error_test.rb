@@ -0,0 +1,31 @@
+# frozen_string_literal: true
+
+require_relative "test_helper"
+
+class ErrorTest < Minitest::Test
+  include FMODTestHelper
+
+  def test_core_method_name
+    system = build_system
+
+    error = assert_raises(FMOD::InvalidParamError) { system.get_channel(99_999) }
+    assert_equal "FMOD::System#get_channel", error.method_name
+    assert_equal FMOD::InvalidParamError::CODE, error.code
+  end
+
+  def test_studio_method_name
+    system = build_studio_system
+
+    error = assert_raises(FMOD::Error) { system.get_bank("bank:/missing") }
+    assert_equal "FMOD::Studio::System#get_bank", error.method_name
+  end
+
+  def test_use_after_free_method_name
+    system = build_system
+    group = system.create_sound_group("group")
+    group.release
+
+    error = assert_raises(FMOD::UseAfterFreeError) { group.get_name }
+    assert_equal "FMOD::SoundGroup#get_name", error.method_name
+  end
+end
@@ -0,0 +1,49 @@
+# frozen_string_literal: true
+
+require_relative "test_helper"
+
+class ExternStructStorageTest < Minitest::Test
+  include FMODTestHelper
+
+  # FMOD frees a connection when it's disconnected, and is free to hand the same address out again.
+  # a new connection must never come back as the old connection's wrapper
+  def test_reused_handle_gets_a_new_wrapper
+    system = build_system
+    output = system.create_dsp_by_type(FMOD::DspType::Mixer)
+    input = system.create_dsp_by_type(FMOD::DspType::Mixer)
+
+    10.times do |i|
+      connection = output.add_input(input, FMOD::DspConnectionType::Standard)
+      assert_nil connection.get_userdata, "connection #{i} got a stale wrapper"
+
+      connection.set_userdata(i)
+      output.disconnect_from(input, connection)
+    end
+  end
+
+  def test_wrapping_while_gc_runs
+    system = build_system
+
+    # every allocation starts a GC, which marks the storage while the wrapper is being stored
+    GC.stress = true
+    groups = Array.new(3) { |i| system.create_sound_group("group #{i}") }
+    GC.stress = false
+
+    assert_equal ["group 0", "group 1", "group 2"], groups.map(&:get_name)
+  ensure
+    GC.stress = false
+  end
+
+  def test_collected_ruby_owned_handles_are_released
+    system = build_system
+    before = FMOD::ExternStructStorage.stats.fetch(:sound_group, 0)
+
+    100.times { |i| system.create_sound_group("owned #{i}").set_owned_by_ruby(true) }
+    GC.start
+    system.update
+    FMOD::ExternStructStorage.cleanup
+
+    # the GC is conservative, so a few may still be around
+    assert_operator FMOD::ExternStructStorage.stats.fetch(:sound_group, 0), :<, before + 100
+  end
+end
@@ -0,0 +1,21 @@
+# frozen_string_literal: true
+
+require_relative "test_helper"
+
+class ForkTest < Minitest::Test
+  include FMODTestHelper
+
+  def test_child_exit_leaves_parent_systems_alone
+    skip "fork isn't available" unless Process.respond_to?(:fork)
+
+    system = build_system
+    group = system.create_sound_group("group")
+
+    # the child exits normally, running the at_exit hooks it inherited
+    pid = fork {}
+    Process.wait(pid)
+
+    assert_equal "group", group.get_name
+    system.update
+  end
+end
@@ -0,0 +1,25 @@
+# frozen_string_literal: true
+
+require_relative "test_helper"
+
+class SoundBuilderTest < Minitest::Test
+  USER_SOUND = { channels: 1, frequency: 8_000, format: FMOD::SoundFormat::PCM16, length: 16_000 }.freeze
+
+  def test_open_user_with_options
+    builder = FMOD::SoundBuilder.open_user(**USER_SOUND, max_polyphony: 3)
+
+    assert_equal 3, builder.max_polyphony
+    assert_equal 16_000, builder.length
+  end
+
+  def test_open_user_from_preset
+    preset = FMOD::SoundBuilder.preset(:user_test, max_polyphony: 4)
+
+    assert_equal 4, preset.open_user(**USER_SOUND).max_polyphony
+    assert_equal 4, FMOD::SoundBuilder.open_user(**USER_SOUND, preset: :user_test).max_polyphony
+  end
+
+  def test_open_user_rejects_unknown_options
+    assert_raises(ArgumentError) { FMOD::SoundBuilder.open_user(**USER_SOUND, bogus: 1) }
+  end
+end
@@ -0,0 +1,25 @@
+# frozen_string_literal: true
+
+require_relative "test_helper"
+
+class SoundTest < Minitest::Test
+  include FMODTestHelper
+
+  def setup
+    @system = build_system
+    # a second of 8kHz mono silence, decoded into memory so it can be locked
+    builder = FMOD::SoundBuilder.open_user(channels: 1, frequency: 8_000, format: FMOD::SoundFormat::PCM16,
+                                           length: 16_000)
+    @sound = @system.create_sound(builder)
+  end
+
+  def test_lock_writes_back
+    @sound.lock(0, 4) { |part, _| part.replace("\x01\x02\x03\x04".b) }
+
+    assert_equal "\x01\x02\x03\x04".b, @sound.lock(0, 4).first
+  end
+
+  def test_release_while_locked
+    assert_raises(FMOD::UseAfterFreeError) { @sound.lock(0, 4) { @sound.release } }
+  end
+end
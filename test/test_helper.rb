@@ -0,0 +1,38 @@
+# frozen_string_literal: true
+
+$LOAD_PATH.unshift File.expand_path("../lib", __dir__)
+require "libfmod"
+
+require "benchmark"
+require "minitest/autorun"
+
+module FMODTestHelper
+  # A core system that doesn't need an audio device, and only mixes when updated.
+  def build_system
+    builder = FMOD::SystemBuilder.new
+    builder.output(FMOD::OutputType::NoSoundNRT)
+    system = builder.build(64, FMOD::InitFlags::NORMAL)
+    systems << system
+    system
+  end
+
+  def build_studio_system(studio_flags = FMOD::Studio::InitFlags::NORMAL)
+    builder = FMOD::Studio::SystemBuilder.new
+    system = builder.build(64, studio_flags, FMOD::InitFlags::NORMAL)
+    systems << system
+    system
+  end
+
+  def systems
+    @systems ||= []
+  end
+
+  def teardown
+    systems.reverse_each do |system|
+      system.release
+    rescue FMOD::UseAfterFreeError
+      # released by the test itself
+    end
+    super
+  end
+end